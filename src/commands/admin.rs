@@ -0,0 +1,275 @@
+use chrono::Utc;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+
+use crate::{
+    calculate_hash::calculate_hash,
+    config::Config,
+    error::{self, Error},
+    state,
+    structs::{
+        admin::{AdminData, AdminResponse, ClientInfo, RunningQueryInfo},
+        common::{Command, CommandData},
+    },
+    util::response::{send_failure, send_response, Outgoing},
+};
+
+pub async fn admin(write: Outgoing, command: &Command, users: Arc<Mutex<state::Store>>) {
+    let admin_data = match &command.data {
+        CommandData::Admin(admin_data) => admin_data,
+        _ => {
+            warn!("A User sent an invalid command: {:?}", command);
+
+            send_failure(&write, &Error::InvalidData("Invalid data for admin".to_string()), command.nonce.clone()).await;
+
+            return;
+        }
+    };
+
+    let config = Arc::clone(&users.lock().await.config);
+
+    if let Err(error) = authorize(&config, &admin_data.token) {
+        warn!("A User sent an admin command: {}", error);
+
+        send_failure(&write, &error, command.nonce.clone()).await;
+
+        return;
+    }
+
+    let response = match run(admin_data, &users).await {
+        Ok(response) => response,
+        Err(error) => AdminResponse {
+            success: false,
+            error: Some(error.to_string()),
+            warnings: Vec::new(),
+            clients: None,
+            queries: None,
+        },
+    };
+
+    send_response(&write, "admin", CommandData::AdminResponse(response), command.nonce.clone()).await;
+}
+
+// ? Same as the profile tokens, the config only holds the sha512 of the tokens
+fn authorize(config: &Config, token: &str) -> error::Result<()> {
+    if config.admin_tokens.is_empty() {
+        return Err(Error::Forbidden("Admin commands are disabled".to_string()));
+    }
+
+    let hash = calculate_hash(token.to_string());
+
+    if !config.admin_tokens.iter().any(|token| token.to_lowercase() == hash) {
+        return Err(Error::Forbidden("Invalid admin token".to_string()));
+    }
+
+    Ok(())
+}
+
+async fn run(admin_data: &AdminData, users: &Arc<Mutex<state::Store>>) -> error::Result<AdminResponse> {
+    // ? The store lock is only held to clone the clients out, every client is then locked on its own
+    let clients: Vec<Arc<Mutex<state::ClientState>>> = users.lock().await.clients.values().cloned().collect();
+
+    match admin_data.action.as_str() {
+        "clients" => {
+            let mut infos = Vec::new();
+
+            for client in clients {
+                infos.push(client_info(&*client.lock().await));
+            }
+
+            infos.sort_by(|a, b| a.connected_at.cmp(&b.connected_at));
+
+            Ok(AdminResponse {
+                success: true,
+                error: None,
+                warnings: Vec::new(),
+                clients: Some(infos),
+                queries: None,
+            })
+        }
+        "queries" => {
+            let mut queries = Vec::new();
+
+            for client in clients {
+                queries.extend(running_queries(&*client.lock().await));
+            }
+
+            queries.sort_by_key(|query| query.id);
+
+            Ok(AdminResponse {
+                success: true,
+                error: None,
+                warnings: Vec::new(),
+                clients: None,
+                queries: Some(queries),
+            })
+        }
+        "kick" => {
+            let id = admin_data.client.as_deref().ok_or_else(|| invalid("kick needs a client".to_string()))?;
+            let client = users
+                .lock()
+                .await
+                .clients
+                .get(id)
+                .cloned()
+                .ok_or_else(|| invalid(format!("Unknown client: {}", id)))?;
+
+            let client = client.lock().await;
+
+            for abort in client.running.values().filter_map(|query| query.abort.as_ref()) {
+                abort.abort();
+            }
+
+            // ? notify_one keeps the permit, so this works even if the connection isn't waiting right now
+            client.kick.notify_one();
+
+            info!(client = id, "Client kicked by an admin");
+
+            Ok(AdminResponse {
+                success: true,
+                error: None,
+                warnings: Vec::new(),
+                clients: None,
+                queries: None,
+            })
+        }
+        "cancel" => {
+            let id = admin_data.query.ok_or_else(|| invalid("cancel needs a query".to_string()))?;
+
+            for client in clients {
+                let client = client.lock().await;
+
+                if let Some(query) = client.running.get(&id) {
+                    let abort = query
+                        .abort
+                        .as_ref()
+                        .ok_or_else(|| invalid(format!("{} can't be cancelled", query.command)))?;
+
+                    abort.abort();
+
+                    info!(client = %client.id, query = id, "Query cancelled by an admin");
+
+                    return Ok(AdminResponse {
+                        success: true,
+                        error: None,
+                        warnings: Vec::new(),
+                        clients: None,
+                        queries: None,
+                    });
+                }
+            }
+
+            Err(invalid(format!("Unknown query: {}", id)))
+        }
+        action => Err(invalid(format!("Unknown admin action: {}", action))),
+    }
+}
+
+fn invalid(message: String) -> Error {
+    Error::InvalidData(message)
+}
+
+fn client_info(client: &state::ClientState) -> ClientInfo {
+    ClientInfo {
+        id: client.id.clone(),
+        peer: client.peer.clone(),
+        common_name: client.identity.as_ref().and_then(|identity| identity.common_name.clone()),
+        connected_at: client.connected_at.to_rfc3339(),
+        connected: client.connected,
+        keyspace: client.keyspace.clone(),
+        in_flight: client.running.len(),
+        total_queries: client.total_queries,
+        queries: running_queries(client),
+    }
+}
+
+fn running_queries(client: &state::ClientState) -> Vec<RunningQueryInfo> {
+    let now = Utc::now();
+
+    let mut queries: Vec<RunningQueryInfo> = client
+        .running
+        .iter()
+        .map(|(id, query)| RunningQueryInfo {
+            id: *id,
+            client: client.id.clone(),
+            command: query.command.clone(),
+            keyspace: query.keyspace.clone(),
+            table: query.table.clone(),
+            nonce: query.nonce.clone(),
+            cql: query.cql.clone(),
+            cancellable: query.abort.is_some(),
+            started_at: query.started_at.to_rfc3339(),
+            elapsed_ms: (now - query.started_at).num_microseconds().unwrap_or_default() as f64 / 1000.0,
+        })
+        .collect();
+
+    queries.sort_by_key(|query| query.id);
+
+    queries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{authorize, run};
+    use crate::{
+        calculate_hash::calculate_hash,
+        config::Config,
+        state::{ClientState, Store},
+        structs::{admin::AdminData, common::Command},
+    };
+    use futures_util::future::AbortHandle;
+    use std::sync::Arc;
+    use tokio::sync::Mutex;
+
+    fn config(admin_tokens: &[&str]) -> Config {
+        serde_json::from_value(serde_json::json!({ "listeners": [], "adminTokens": admin_tokens })).unwrap()
+    }
+
+    fn command(name: &str) -> Command {
+        serde_json::from_value(serde_json::json!({ "hash": "", "command": name, "length": 0, "data": {} })).unwrap()
+    }
+
+    fn cancel(query: u64) -> AdminData {
+        AdminData {
+            token: "secret".to_string(),
+            action: "cancel".to_string(),
+            client: None,
+            query: Some(query),
+        }
+    }
+
+    #[test]
+    fn admin_tokens_are_compared_by_hash() {
+        let hash = calculate_hash("secret".to_string());
+
+        let error = |config: Config, token: &str| authorize(&config, token).unwrap_err().to_string();
+
+        assert_eq!(error(config(&[]), "secret"), "Admin commands are disabled");
+        assert_eq!(error(config(&[&hash]), "other"), "Invalid admin token");
+        assert_eq!(error(config(&[&hash]), &hash), "Invalid admin token");
+        assert!(authorize(&config(&[&hash.to_uppercase()]), "secret").is_ok());
+    }
+
+    #[tokio::test]
+    async fn only_cancellable_commands_are_cancelled() {
+        let users = Arc::new(Mutex::new(Store::new(Arc::new(config(&[])))));
+        let client = Arc::new(Mutex::new(ClientState::new(false, "", None)));
+
+        let (abort, _registration) = AbortHandle::new_pair();
+        let connect = client.lock().await.start_query(&command("connect"), None);
+        let select = client.lock().await.start_query(&command("select"), Some(abort.clone()));
+
+        users.lock().await.clients.insert("1".to_string(), client);
+
+        let error = run(&cancel(connect), &users).await.unwrap_err();
+
+        assert_eq!(error.to_string(), "connect can't be cancelled");
+        assert!(!abort.is_aborted());
+
+        assert!(run(&cancel(select), &users).await.unwrap().success);
+        assert!(abort.is_aborted());
+
+        assert!(run(&cancel(select + 100), &users).await.is_err());
+    }
+}
@@ -1,116 +1,193 @@
-use futures_util::{stream::SplitSink, SinkExt};
 use std::sync::Arc;
-use tokio::{net::TcpStream, sync::Mutex};
-use tokio_tungstenite::{tungstenite::Message, WebSocketStream};
+use tokio::sync::Mutex;
+use tracing::warn;
 
 use crate::{
+    calculate_hash::calculate_hash,
+    error::{self, Error},
+    metrics,
     state,
-    structs::common::{Command, CommandData, QueryResult},
-    LOGGING,
+    structs::{
+        common::{Command, CommandData},
+        connect::{ConnectData, ConnectResponse},
+    },
+    util::{
+        response::{close, send_failure, send_response, Outgoing},
+        session::{build_session, session_key, ScyllaSession},
+    },
 };
 
+// ? Turns whatever the client sent into the settings we build the session with
+pub async fn resolve_connect_data(
+    data: &CommandData,
+    users: &Arc<Mutex<state::Store>>,
+) -> error::Result<ConnectData> {
+    let config = Arc::clone(&users.lock().await.config);
+
+    let mut connect_data = match data {
+        CommandData::Connect(connect_data) => {
+            if !config.allow_client_credentials {
+                return Err(Error::Forbidden("Connecting with credentials is disabled, use a profile".to_string()));
+            }
+
+            // ? The TLS options name files on the server and can turn off verification, so only profiles can set them
+            if connect_data.options.as_ref().is_some_and(|options| options.tls.is_some()) {
+                return Err(Error::Forbidden("TLS to the cluster can only be set in a profile".to_string()));
+            }
+
+            connect_data.clone()
+        }
+        CommandData::ConnectProfile(profile_data) => {
+            let profile = config
+                .profiles
+                .get(&profile_data.profile)
+                .ok_or_else(|| Error::InvalidData(format!("Unknown profile: {}", profile_data.profile)))?;
+
+            // ? The config only holds the sha512 of the tokens, so a leaked config doesn't leak the tokens
+            if !profile.public {
+                let tokens = profile.tokens.as_deref().unwrap_or_default();
+                let token = profile_data.token.as_deref().unwrap_or_default();
+
+                if !tokens.iter().any(|hash| hash.to_lowercase() == calculate_hash(token.to_string())) {
+                    return Err(Error::Forbidden("Invalid token for profile".to_string()));
+                }
+            }
+
+            let mut connect_data = profile.connect.clone();
+
+            if let Some(keyspace) = &profile_data.keyspace {
+                connect_data.keyspace = keyspace.to_string();
+            }
+
+            connect_data
+        }
+        _ => return Err(Error::InvalidData("Invalid data for connect".to_string())),
+    };
+
+    // ? Done before building the session key, so sessions with different profiles aren't shared
+    if !config.execution_profiles.is_empty() {
+        let profiles = connect_data.execution_profiles.get_or_insert_with(Default::default);
+
+        for (name, options) in config.execution_profiles.iter() {
+            profiles.entry(name.to_string()).or_insert_with(|| options.clone());
+        }
+    }
+
+    Ok(connect_data)
+}
+
+// ? Reuses a shared session if there is one, the caller has to release the returned key once it's done with it
+pub async fn open_session(
+    connect_data: &ConnectData,
+    users: &Arc<Mutex<state::Store>>,
+) -> error::Result<(Arc<ScyllaSession>, String)> {
+    let key = session_key(connect_data);
+    let existing = users.lock().await.acquire_session(&key);
+
+    if let Some(session) = existing {
+        return Ok((session, key));
+    }
+
+    // ? Building a session can take a while, so we don't hold the store while doing it
+    let session = build_session(connect_data).await?;
+
+    Ok((users.lock().await.insert_session(&key, session), key))
+}
+
 pub async fn connect(
-    write: Arc<Mutex<SplitSink<WebSocketStream<TcpStream>, Message>>>,
-    command: CommandData,
+    write: Outgoing,
+    raw_command: &Command,
     user: Arc<Mutex<state::ClientState>>,
+    users: Arc<Mutex<state::Store>>,
 ) {
-    let mut write = write.lock().await;
-    let mut uu = user.lock().await;
-
-    if uu.connected {
-        let error = Command {
-            command: "error".to_string(),
-            data: CommandData::SelectResponse(QueryResult {
-                error: Some("Already connected to scylla".to_string()),
-                result: Vec::new(),
-            }),
-            keyspace: None,
-            table: None,
-            hash: "".to_string(),
-            length: "".len(),
-            nonce: None,
-        };
-
-        let response = serde_json::to_string(&error).unwrap();
-
-        match write.send(Message::Text(response)).await {
-            // ? we don't care about if it succeeds or not
-            _ => {}
-        }
+    let connection_lock = Arc::clone(&user.lock().await.connection_lock);
+    let _connection_guard = connection_lock.lock().await;
 
-        match write.close().await {
-            _ => {}
-        }
+    if user.lock().await.connected {
+        send_failure(&write, &Error::AlreadyConnected, raw_command.nonce.clone()).await;
+
+        close(&write).await;
 
         return;
     }
 
-    match command {
-        CommandData::Connect(connect_data) => {
-            uu.keyspace = connect_data.keyspace;
-
-            match scylla::SessionBuilder::new()
-                .known_node(connect_data.contact_points[0].as_str())
-                .user(
-                    connect_data.credentials.username,
-                    connect_data.credentials.password,
-                )
-                .build()
-                .await
-            {
-                Ok(session) => {
-                    uu.session = Some(Arc::new(Mutex::new(session)));
-                    uu.connected = true;
-
-                    let response = Command {
-                        command: "connect".to_string(),
-                        data: CommandData::ConnectResponse(crate::structs::connect::ConnectResponse {
-                            result: "Connected to scylla".to_string(),
-                            error: None,
-                        }),
-                        keyspace: None,
-                        table: None,
-                        hash: "".to_string(),
-                        length: "".len(),
-                        nonce: None,
-                    };
-
-                    match write.send(Message::Text(serde_json::to_string(&response).unwrap())).await {
-                        // ? we don't care about if it succeeds or not
-                        _ => {}
-                    }
-                }
-                Err(error) => {
-                    uu.connected = false;
-
-                    let response = Command {
-                        command: "connect".to_string(),
-                        data: CommandData::ConnectResponse(crate::structs::connect::ConnectResponse {
-                            result: "Failed to connect to scylla".to_string(),
-                            error: Some(error.to_string()),
-                        }),
-                        keyspace: None,
-                        table: None,
-                        hash: "".to_string(),
-                        length: "".len(),
-                        nonce: None,
-                    };
-
-                    match write.send(Message::Text(serde_json::to_string(&response).unwrap())).await {
-                        // ? we don't care about if it succeeds or not
-                        _ => {}
-                    }
-
-                    match write.close().await {
-                        _ => {}
-                    }
-                }
-            }
+    let connect_data = match resolve_connect_data(&raw_command.data, &users).await {
+        Ok(connect_data) => connect_data,
+        Err(error) => {
+            warn!("A User failed to connect: {}", error);
+
+            metrics::record_error(error.code());
+
+            send_response(
+                &write,
+                "connect",
+                CommandData::ConnectResponse(ConnectResponse {
+                    result: "Failed to connect to scylla".to_string(),
+                    error: Some(error.to_string()),
+                    warnings: Vec::new(),
+                }),
+                raw_command.nonce.clone(),
+            )
+            .await;
+
+            return;
         }
-        _ => {
-            if *LOGGING.lock().await {
-                println!("[Warn] A User sent an invalid command: {:?}", command);
+    };
+
+    match open_session(&connect_data, &users).await {
+        Ok((session, key)) => {
+            // ? The connection can close before we got the lock, its cleanup has already run then and won't release
+            // ? the session we'd store on it
+            let client_id = user.lock().await.id.clone();
+            let mut store = users.lock().await;
+
+            if !store.clients.contains_key(&client_id) {
+                store.release_session(&key);
+
+                return;
             }
+
+            drop(store);
+
+            let mut uu = user.lock().await;
+
+            uu.keyspace = connect_data.keyspace.clone();
+            uu.session = Some(session);
+            uu.session_key = Some(key);
+            uu.connected = true;
+
+            drop(uu);
+
+            send_response(
+                &write,
+                "connect",
+                CommandData::ConnectResponse(ConnectResponse {
+                    result: "Connected to scylla".to_string(),
+                    error: None,
+                    warnings: Vec::new(),
+                }),
+                raw_command.nonce.clone(),
+            )
+            .await;
+        }
+        Err(error) => {
+            user.lock().await.connected = false;
+
+            metrics::record_error(error.code());
+
+            send_response(
+                &write,
+                "connect",
+                CommandData::ConnectResponse(ConnectResponse {
+                    result: "Failed to connect to scylla".to_string(),
+                    error: Some(error.to_string()),
+                    warnings: Vec::new(),
+                }),
+                raw_command.nonce.clone(),
+            )
+            .await;
+
+            close(&write).await;
         }
     }
 }
@@ -0,0 +1,65 @@
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+use crate::{
+    state,
+    structs::{
+        common::{Command, CommandData},
+        connect::ConnectResponse,
+    },
+    util::response::{send_response, Outgoing},
+};
+
+// ? Drops the session but keeps the socket open, so the client can connect again later
+pub async fn disconnect(
+    write: Outgoing,
+    raw_command: &Command,
+    user: Arc<Mutex<state::ClientState>>,
+    users: Arc<Mutex<state::Store>>,
+) {
+    let connection_lock = Arc::clone(&user.lock().await.connection_lock);
+    let _connection_guard = connection_lock.lock().await;
+
+    let session_key = {
+        let mut uu = user.lock().await;
+
+        if !uu.connected {
+            drop(uu);
+
+            send_response(
+                &write,
+                "disconnect",
+                CommandData::ConnectResponse(ConnectResponse {
+                    result: "Failed to disconnect from scylla".to_string(),
+                    error: Some("Not connected to Scylla".to_string()),
+                    warnings: Vec::new(),
+                }),
+                raw_command.nonce.clone(),
+            )
+            .await;
+
+            return;
+        }
+
+        uu.connected = false;
+        uu.session = None;
+        uu.session_key.take()
+    };
+
+    // ? Queries that are still running keep their own handle to the session, so they finish normally
+    if let Some(key) = session_key {
+        users.lock().await.release_session(&key);
+    }
+
+    send_response(
+        &write,
+        "disconnect",
+        CommandData::ConnectResponse(ConnectResponse {
+            result: "Disconnected from scylla".to_string(),
+            error: None,
+            warnings: Vec::new(),
+        }),
+        raw_command.nonce.clone(),
+    )
+    .await;
+}
@@ -0,0 +1,75 @@
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tracing::warn;
+
+use crate::{
+    error::Error,
+    state,
+    structs::{
+        common::{Command, CommandData},
+        handshake::HandshakeResponse,
+    },
+    util::{
+        encoding::Encoding,
+        response::{send_failure, send_response, Outgoing},
+    },
+};
+
+pub async fn handshake(write: Outgoing, raw_command: &Command, user: &Arc<Mutex<state::ClientState>>) {
+    // ? The responses of commands that are still running would go out in the new encoding before the client expects it
+    let running = user.lock().await.running.len();
+
+    if running > 0 {
+        let error = Error::InvalidData(format!("Can't switch the encoding while {} commands are running", running));
+
+        send_failure(&write, &error, raw_command.nonce.clone()).await;
+
+        return;
+    }
+
+    match &raw_command.data {
+        CommandData::Handshake(handshake_data) => match Encoding::from_name(&handshake_data.encoding) {
+            Some(encoding) => {
+                // ? The response is sent with the new encoding, so the client knows it took effect
+                *write.encoding.lock().await = encoding;
+
+                send_response(
+                    &write,
+                    "handshake",
+                    CommandData::HandshakeResponse(HandshakeResponse {
+                        encoding: encoding.name().to_string(),
+                        error: None,
+                        warnings: Vec::new(),
+                    }),
+                    raw_command.nonce.clone(),
+                )
+                .await;
+            }
+            None => {
+                let encoding = *write.encoding.lock().await;
+
+                send_response(
+                    &write,
+                    "handshake",
+                    CommandData::HandshakeResponse(HandshakeResponse {
+                        encoding: encoding.name().to_string(),
+                        error: Some(format!("Unknown encoding: {}", handshake_data.encoding)),
+                        warnings: Vec::new(),
+                    }),
+                    raw_command.nonce.clone(),
+                )
+                .await;
+            }
+        },
+        _ => {
+            warn!("A User sent an invalid command: {:?}", raw_command.data);
+
+            send_failure(
+                &write,
+                &Error::InvalidData("Invalid data for handshake".to_string()),
+                raw_command.nonce.clone(),
+            )
+            .await;
+        }
+    }
+}
@@ -1,132 +1,177 @@
-use futures_util::{stream::SplitSink, SinkExt};
 use std::sync::Arc;
-use tokio::{net::TcpStream, sync::Mutex};
-use tokio_tungstenite::{tungstenite::Message, WebSocketStream};
+use tokio::sync::Mutex;
+use tracing::{debug, warn};
 
 use crate::{
-    calculate_hash, state, structs::{
-        common::{Command, CommandData, QueryResult},
+    error::Error,
+    metrics, state,
+    structs::{
+        common::{Command, CommandData},
         insert::InsertResponse,
-    }, util::queries::insert_query, LOGGING
+    },
+    util::{
+        audit::{self, AuditEvent},
+        queries::insert_query,
+        response::{close, send_failure, send_response_with_stats, Outgoing},
+        slow_log::{self, SlowQuery},
+        statement::{build_statement, execute},
+    },
 };
 
 pub async fn insert(
-    write: Arc<Mutex<SplitSink<WebSocketStream<TcpStream>, Message>>>,
+    write: Outgoing,
     command: &CommandData,
     user: Arc<Mutex<state::ClientState>>,
     keyspace: &Option<String>,
     table: &Option<String>,
     raw_command: &Command,
 ) {
-    let user = user.lock().await;
-
-    if !user.connected {
-        let error = Command {
-            command: "error".to_string(),
-            data: CommandData::SelectResponse(QueryResult {
-                error: Some("Not connected to Scylla".to_string()),
-                result: Vec::new(),
-            }),
-            keyspace: None,
-            table: None,
-            hash: "".to_string(),
-            length: "".len(),
-            nonce: None,
-        };
-
-        let response = serde_json::to_string(&error).unwrap();
-
-        let mut write = write.lock().await;
-
-        match write.send(Message::Text(response)).await {
-            // ? we don't care about if it succeeds or not
-            _ => {}
-        }
+    let (snapshot, connection_id) = {
+        let user = user.lock().await;
 
-        match write.close().await {
-            _ => {}
-        }
+        (user.session(), user.id.clone())
+    };
 
-        return;
-    }
+    let (session, user_keyspace) = match snapshot {
+        Some(snapshot) => snapshot,
+        None => {
+            send_failure(&write, &Error::NotConnected, raw_command.nonce.clone()).await;
+
+            close(&write).await;
+
+            return;
+        }
+    };
 
     match command {
         CommandData::Insert(insert_data) => {
-            let session = user.session.as_ref().unwrap().lock().await;
+            let table = match table {
+                Some(table) => table,
+                None => {
+                    let error = Error::InvalidData("A table is required for insert".to_string());
+
+                    send_failure(&write, &error, raw_command.nonce.clone()).await;
 
-            let table = table.as_ref().unwrap();
+                    return;
+                }
+            };
             let keyspace = keyspace.as_ref();
-            let user_keyspace = &user.keyspace;
 
             let query = insert_query(
-                &keyspace.to_owned().unwrap_or(user_keyspace),
+                keyspace.unwrap_or(&user_keyspace),
                 table,
                 insert_data,
             );
 
-            match session.query(query.query, query.values).await {
-                Ok(_) => {
-                    println!("Insert successful");
-                    let mut response = Command {
-                        command: "insert".to_string(),
-                        data: CommandData::InsertResponse(InsertResponse {
+            let statement = match build_statement(&query.query, raw_command, &session, false) {
+                Ok(statement) => statement,
+                Err(error) => {
+                    send_failure(&write, &error, raw_command.nonce.clone()).await;
+
+                    return;
+                }
+            };
+
+            let audited_keyspace = keyspace.unwrap_or(&user_keyspace);
+
+            let audit = if audit::enabled() {
+                audit::attempt(
+                    &user,
+                    AuditEvent {
+                        command: "insert",
+                        kind: "insert",
+                        nonce: raw_command.nonce.as_deref(),
+                        keyspace: audited_keyspace,
+                        table: Some(table),
+                        primary_key: audit::primary_key(&session, audited_keyspace, table, &insert_data.columns),
+                        cql: &query.query,
+                    },
+                )
+                .await
+            } else {
+                None
+            };
+
+            let execution = execute(&session, statement, &query.values).await;
+            let audit_error = execution.result.as_ref().err().map(|error| error.to_string());
+
+            let (response_bytes, rows) = match execution.result {
+                Ok(query_result) => {
+                    debug!("Insert successful");
+
+                    let stats = execution.timing.stats(raw_command, &execution.info, 0);
+
+                    let response_bytes = send_response_with_stats(
+                        &write,
+                        "insert",
+                        CommandData::InsertResponse(InsertResponse {
                             error: None,
                             success: true,
+                            warnings: query_result.warnings,
+                            execution: Some(execution.info),
+                            tracing: execution.tracing,
+                            stats: None,
                         }),
-                        keyspace: None,
-                        table: None,
-                        hash: "".to_string(),
-                        length: "".len(),
-                        nonce: raw_command.nonce.clone(), // todo: do not clone
-                    };
-
-                    let string_response = serde_json::to_string(&response.data).unwrap();
-
-                    response.length = string_response.len() + response.command.len();
+                        raw_command.nonce.clone(),
+                        stats,
+                    )
+                    .await;
 
-                    response.hash = calculate_hash(
-                        response.command.to_string()
-                            + &response.length.to_string()
-                            + &string_response,
-                    );
+                    (response_bytes, 0)
+                }
+                Err(error) => {
+                    let error = Error::from(error);
 
-                    let response = serde_json::to_string(&response).unwrap();
+                    metrics::record_error(error.code());
 
-                    let mut write = write.lock().await;
+                    let stats = execution.timing.stats(raw_command, &execution.info, 0);
 
-                    match write.send(Message::Text(response)).await {
-                        _ => {}
-                    }
-                }
-                Err(error) => {
-                    let response = Command {
-                        command: "insert".to_string(),
-                        data: CommandData::InsertResponse(InsertResponse {
+                    let response_bytes = send_response_with_stats(
+                        &write,
+                        "insert",
+                        CommandData::InsertResponse(InsertResponse {
                             error: Some(error.to_string()),
                             success: false,
+                            warnings: Vec::new(),
+                            execution: Some(execution.info),
+                            tracing: execution.tracing,
+                            stats: None,
                         }),
-                        keyspace: None,
-                        table: None,
-                        hash: "".to_string(),
-                        length: "".len(),
-                        nonce: None,
-                    };
-
-                    let response = serde_json::to_string(&response).unwrap();
+                        raw_command.nonce.clone(),
+                        stats,
+                    )
+                    .await;
 
-                    let mut write = write.lock().await;
-
-                    match write.send(Message::Text(response)).await {
-                        _ => {}
-                    }
+                    (response_bytes, 0)
                 }
+            };
+
+            slow_log::record(SlowQuery {
+                connection: &connection_id,
+                command: "insert",
+                nonce: raw_command.nonce.as_deref(),
+                cql: &query.query,
+                values: &query.values,
+                rows,
+                response_bytes,
+                duration: execution.duration,
+            })
+            .await;
+
+            if let Some(audit) = audit {
+                audit.finish(audit_error).await;
             }
         }
 
         _ => {
-            if *LOGGING.lock().await {
-                println!("[Warn] A User sent an invalid command: {:?}", command);
-            }
+            warn!("A User sent an invalid command: {:?}", command);
+
+            send_failure(
+                &write,
+                &Error::InvalidData("Invalid data for insert".to_string()),
+                raw_command.nonce.clone(),
+            )
+            .await;
         }
     }
 }
@@ -1,4 +1,9 @@
+pub mod admin;
 pub mod connect;
+pub mod disconnect;
+pub mod handshake;
 pub mod insert;
 pub mod select;
-pub mod raw;
\ No newline at end of file
+pub mod raw;
+pub mod reconnect;
+pub mod use_keyspace;
@@ -1,81 +1,115 @@
-use futures_util::{stream::SplitSink, SinkExt};
 use indexmap::IndexMap;
 use std::sync::Arc;
-use tokio::{net::TcpStream, sync::Mutex};
-use tokio_tungstenite::{tungstenite::Message, WebSocketStream};
+use tokio::sync::Mutex;
+use tracing::warn;
 
 use crate::{
-    calculate_hash, state, structs::
-        common::{Command, CommandData, QueryResult, Value}, util::{parse_cql_value::parse_cql_value, queries::raw_query}, LOGGING
+    error::Error,
+    metrics, state,
+    structs::common::{Command, CommandData, QueryResult, Value},
+    util::{
+        audit::{self, AuditEvent},
+        parse_cql_value::parse_cql_value,
+        queries::raw_query,
+        response::{close, send_failure, send_response_with_stats, Outgoing},
+        slow_log::{self, SlowQuery},
+        statement::{build_statement, execute},
+    },
 };
 
 pub async fn raw(
-    write: Arc<Mutex<SplitSink<WebSocketStream<TcpStream>, Message>>>,
+    write: Outgoing,
     command: &CommandData,
     user: Arc<Mutex<state::ClientState>>,
     _: &Option<String>,
     _: &Option<String>,
     raw_command: &Command,
 ) {
-    let user = user.lock().await;
-
-    if !user.connected {
-        let error = Command {
-            command: "error".to_string(),
-            data: CommandData::SelectResponse(QueryResult {
-                error: Some("Not connected to Scylla".to_string()),
-                result: Vec::new(),
-            }),
-            keyspace: None,
-            table: None,
-            hash: "".to_string(),
-            length: "".len(),
-            nonce: None,
-        };
-
-        let response = serde_json::to_string(&error).unwrap();
-
-        let mut write = write.lock().await;
-
-        match write.send(Message::Text(response)).await {
-            // ? we don't care about if it succeeds or not
-            _ => {}
-        }
+    let (snapshot, connection_id) = {
+        let user = user.lock().await;
 
-        match write.close().await {
-            _ => {}
-        }
+        (user.session(), user.id.clone())
+    };
 
-        return;
-    }
+    let (session, user_keyspace) = match snapshot {
+        Some(snapshot) => snapshot,
+        None => {
+            send_failure(&write, &Error::NotConnected, raw_command.nonce.clone()).await;
+
+            close(&write).await;
+
+            return;
+        }
+    };
 
     match command {
         CommandData::Raw(raw_data) => {
-            let session = user.session.as_ref().unwrap().lock().await;
+            let query = raw_query(&raw_data.query, &raw_data.values, raw_data.limit.to_owned().unwrap_or(0));
 
-            let query = raw_query(&raw_data.query, raw_data.limit.to_owned().unwrap_or(0));
+            let statement = match build_statement(&query.query, raw_command, &session, false) {
+                Ok(statement) => statement,
+                Err(error) => {
+                    send_failure(&write, &error, raw_command.nonce.clone()).await;
 
+                    return;
+                }
+            };
+
+            let kind = audit::classify(&raw_data.query).filter(|_| audit::enabled());
+            // ? Without a keyspace in the statement it runs in the connection's keyspace
+            let target = kind.and_then(|_| audit::target_of(&raw_data.query)).unwrap_or_default();
+            let audited_keyspace = target.keyspace.as_deref().unwrap_or(&user_keyspace);
+
+            let audit = match kind {
+                Some(kind) => {
+                    let primary_key = match (kind, target.table.as_deref()) {
+                        ("insert" | "update" | "delete", Some(table)) => audit::primary_key(
+                            &session,
+                            audited_keyspace,
+                            table,
+                            &audit::statement_values(&raw_data.query, &raw_data.values),
+                        ),
+                        _ => None,
+                    };
 
-            match session.query(query.query, &raw_data.values).await {
+                    audit::attempt(
+                        &user,
+                        AuditEvent {
+                            command: "raw",
+                            kind,
+                            nonce: raw_command.nonce.as_deref(),
+                            keyspace: audited_keyspace,
+                            table: target.table.as_deref(),
+                            primary_key,
+                            cql: &raw_data.query,
+                        },
+                    )
+                    .await
+                }
+                None => None,
+            };
+
+            let execution = execute(&session, statement, &query.values).await;
+            let audit_error = execution.result.as_ref().err().map(|error| error.to_string());
+
+            let (response_bytes, rows) = match execution.result {
                 Ok(query_result) => {
                     let mut result = Vec::new();
 
-                    let mut indexes = Vec::new();
-
                     let query_map = &query_result.col_specs;
 
-                    for value in query_map {
-                        let (value_idx, _) =
-                            query_result.get_column_spec(value.name.as_str()).unwrap();
-
-                        indexes.push(value_idx);
-                    }
+                    let indexes: Vec<usize> = query_map
+                        .iter()
+                        .filter_map(|value| query_result.get_column_spec(value.name.as_str()))
+                        .map(|(value_idx, _)| value_idx)
+                        .collect();
 
-                    for row in query_result.rows.unwrap_or(Vec::new()) {
+                    // ? INSERT, UPDATE, DDL and so on have no rows, they just return an empty result
+                    for row in query_result.rows.unwrap_or_default() {
                         let mut row_vec: IndexMap<String, Value> = IndexMap::new();
 
                         for index in &indexes {
-                            let column = row.columns[index.to_owned()].as_ref();
+                            let column = row.columns.get(index.to_owned()).and_then(Option::as_ref);
                             let name = query_map[index.to_owned()].name.as_str().to_string();
 
                             let value = parse_cql_value(column);
@@ -86,78 +120,82 @@ pub async fn raw(
                         result.push(row_vec);
                     }
 
-                    let mut query_result = Command {
-                        hash: "".to_string(),
-                        length: 0,
-                        command: "raw".to_string(),
-                        keyspace: None,
-                        table: None,
-                        data: CommandData::SelectResponse(QueryResult {
+                    let rows = result.len();
+
+                    let stats = execution.timing.stats(raw_command, &execution.info, rows);
+
+                    let response_bytes = send_response_with_stats(
+                        &write,
+                        "raw",
+                        CommandData::SelectResponse(QueryResult {
                             result,
                             error: None,
+                            warnings: query_result.warnings,
+                            execution: Some(execution.info),
+                            tracing: execution.tracing,
+                            stats: None,
                         }),
-                        nonce: raw_command.nonce.clone(), // todo: do not clone
-                    };
+                        raw_command.nonce.clone(),
+                        stats,
+                    )
+                    .await;
 
-                    let string_query_data = serde_json::to_string(&query_result.data).unwrap();
-
-                    query_result.length = string_query_data.len() + query_result.command.len();
-
-                    query_result.hash = calculate_hash(
-                        query_result.command.to_string()
-                            + &query_result.length.to_string()
-                            + &string_query_data,
-                    );
+                    (response_bytes, rows)
+                }
 
+                Err(e) => {
+                    let e = Error::from(e);
 
-                    let response = serde_json::to_string(&query_result).unwrap();
+                    metrics::record_error(e.code());
 
-                    match write.lock().await.send(Message::Text(response)).await {
-                        _ => {}
-                    }
-                }
+                    let stats = execution.timing.stats(raw_command, &execution.info, 0);
 
-                Err(e) => {
-                    let mut response = Command {
-                        command: "raw".to_string(),
-                        data: CommandData::SelectResponse(QueryResult {
+                    let response_bytes = send_response_with_stats(
+                        &write,
+                        "raw",
+                        CommandData::SelectResponse(QueryResult {
                             error: Some(e.to_string()),
                             result: Vec::new(),
+                            warnings: Vec::new(),
+                            execution: Some(execution.info),
+                            tracing: execution.tracing,
+                            stats: None,
                         }),
-                        keyspace: None,
-                        table: None,
-                        hash: "".to_string(),
-                        length: "".len(),
-                        nonce: None,
-                    };
+                        raw_command.nonce.clone(),
+                        stats,
+                    )
+                    .await;
 
-                    let response_string = serde_json::to_string(&response).unwrap();
-
-                    response.length = response_string.len() + response.command.len();
-
-                    response.hash = calculate_hash(
-                        response.command.to_string()
-                            + &response.length.to_string()
-                            + &response_string,
-                    );
-
-                    let response = serde_json::to_string(&response).unwrap();
-
-                    let mut write = write.lock().await;
-
-                    match write.send(Message::Text(response)).await {
-                        // ? we don't care about if it succeeds or not
-                        _ => {}
-                    }
+                    (response_bytes, 0)
                 }
+            };
+
+            slow_log::record(SlowQuery {
+                connection: &connection_id,
+                command: "raw",
+                nonce: raw_command.nonce.as_deref(),
+                cql: &query.query,
+                values: &query.values,
+                rows,
+                response_bytes,
+                duration: execution.duration,
+            })
+            .await;
+
+            if let Some(audit) = audit {
+                audit.finish(audit_error).await;
             }
-
         }
 
         _ => {
-            if *LOGGING.lock().await {
-                println!("[Warn] A User sent an invalid command: {:?}", command);
-            }
+            warn!("A User sent an invalid command: {:?}", command);
+
+            send_failure(
+                &write,
+                &Error::InvalidData("Invalid data for raw".to_string()),
+                raw_command.nonce.clone(),
+            )
+            .await;
         }
     }
 }
@@ -0,0 +1,100 @@
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tracing::warn;
+
+use crate::{
+    commands::connect::{open_session, resolve_connect_data},
+    error::Error,
+    metrics,
+    state,
+    structs::{
+        common::{Command, CommandData},
+        connect::ConnectResponse,
+    },
+    util::response::{send_response, Outgoing},
+};
+
+async fn send_failure(write: &Outgoing, raw_command: &Command, error: Error) {
+    metrics::record_error(error.code());
+
+    send_response(
+        write,
+        "reconnect",
+        CommandData::ConnectResponse(ConnectResponse {
+            result: "Failed to reconnect to scylla".to_string(),
+            error: Some(error.to_string()),
+            warnings: Vec::new(),
+        }),
+        raw_command.nonce.clone(),
+    )
+    .await;
+}
+
+// ? Swaps the session for one built with the new settings, if that fails the old session is kept
+pub async fn reconnect(
+    write: Outgoing,
+    raw_command: &Command,
+    user: Arc<Mutex<state::ClientState>>,
+    users: Arc<Mutex<state::Store>>,
+) {
+    let connection_lock = Arc::clone(&user.lock().await.connection_lock);
+    let _connection_guard = connection_lock.lock().await;
+
+    let connect_data = match resolve_connect_data(&raw_command.data, &users).await {
+        Ok(connect_data) => connect_data,
+        Err(error) => {
+            warn!("A User failed to reconnect: {}", error);
+
+            send_failure(&write, raw_command, error).await;
+
+            return;
+        }
+    };
+
+    let (session, key) = match open_session(&connect_data, &users).await {
+        Ok(opened) => opened,
+        Err(error) => {
+            send_failure(&write, raw_command, error).await;
+
+            return;
+        }
+    };
+
+    // ? Same as connect, a connection that's already cleaned up would never release it
+    let client_id = user.lock().await.id.clone();
+    let mut store = users.lock().await;
+
+    if !store.clients.contains_key(&client_id) {
+        store.release_session(&key);
+
+        return;
+    }
+
+    drop(store);
+
+    let old_key = {
+        let mut uu = user.lock().await;
+
+        uu.keyspace = connect_data.keyspace.clone();
+        uu.session = Some(session);
+        uu.connected = true;
+        uu.session_key.replace(key)
+    };
+
+    // ? Released after we got the new one, so reconnecting with the same settings doesn't rebuild the session
+    if let Some(old_key) = old_key {
+        users.lock().await.release_session(&old_key);
+    }
+
+    send_response(
+        &write,
+        "reconnect",
+        CommandData::ConnectResponse(ConnectResponse {
+            result: "Reconnected to scylla".to_string(),
+            error: None,
+            warnings: Vec::new(),
+        }),
+        raw_command.nonce.clone(),
+    )
+    .await;
+}
@@ -1,91 +1,95 @@
-use futures_util::{stream::SplitSink, SinkExt};
 use indexmap::IndexMap;
 use std::sync::Arc;
-use tokio::{net::TcpStream, sync::Mutex};
-use tokio_tungstenite::{tungstenite::Message, WebSocketStream};
+use tokio::sync::Mutex;
+use tracing::warn;
 
 use crate::{
-    calculate_hash::calculate_hash,
-    state,
+    error::Error,
+    metrics, state,
     structs::common::{Command, CommandData, QueryResult, Value},
-    util::{parse_cql_value::parse_cql_value, queries::select_query},
-    LOGGING,
+    util::{
+        parse_cql_value::parse_cql_value,
+        queries::select_query,
+        response::{close, send_failure, send_response_with_stats, Outgoing},
+        slow_log::{self, SlowQuery},
+        statement::{build_statement, execute},
+    },
 };
 
 pub async fn select(
-    write: Arc<Mutex<SplitSink<WebSocketStream<TcpStream>, Message>>>,
+    write: Outgoing,
     command: &CommandData,
     user: Arc<Mutex<state::ClientState>>,
     keyspace: &Option<String>,
     table: &Option<String>,
     raw_command: &Command,
 ) {
-    let user = user.lock().await;
-
-    if !user.connected {
-        let error = Command {
-            command: "error".to_string(),
-            data: CommandData::SelectResponse(QueryResult {
-                error: Some("Not connected to Scylla".to_string()),
-                result: Vec::new(),
-            }),
-            keyspace: None,
-            table: None,
-            hash: "".to_string(),
-            length: "".len(),
-            nonce: None,
-        };
-
-        let response = serde_json::to_string(&error).unwrap();
-
-        let mut write = write.lock().await;
-
-        match write.send(Message::Text(response)).await {
-            // ? we don't care about if it succeeds or not
-            _ => {}
-        }
+    let (snapshot, connection_id) = {
+        let user = user.lock().await;
 
-        match write.close().await {
-            _ => {}
-        }
+        (user.session(), user.id.clone())
+    };
 
-        return;
-    }
+    let (session, user_keyspace) = match snapshot {
+        Some(snapshot) => snapshot,
+        None => {
+            send_failure(&write, &Error::NotConnected, raw_command.nonce.clone()).await;
+
+            close(&write).await;
+
+            return;
+        }
+    };
 
     match command {
         CommandData::Select(select_data) => {
-            let table = table.as_ref().unwrap();
+            let table = match table {
+                Some(table) => table,
+                None => {
+                    let error = Error::InvalidData("A table is required for select".to_string());
+
+                    send_failure(&write, &error, raw_command.nonce.clone()).await;
+
+                    return;
+                }
+            };
             let keyspace = keyspace.as_ref();
-            let user_keyspace = &user.keyspace;
 
             let query = select_query(
-                &keyspace.to_owned().unwrap_or(user_keyspace),
+                keyspace.unwrap_or(&user_keyspace),
                 table,
                 select_data,
             );
 
-            let session = user.session.as_ref().unwrap().lock().await;
+            let statement = match build_statement(&query.query, raw_command, &session, true) {
+                Ok(statement) => statement,
+                Err(error) => {
+                    send_failure(&write, &error, raw_command.nonce.clone()).await;
+
+                    return;
+                }
+            };
+
+            let execution = execute(&session, statement, &query.values).await;
 
-            match session.query(query.query, query.values).await {
+            let (response_bytes, rows) = match execution.result {
                 Ok(query_result) => {
                     let mut result = Vec::new();
 
-                    let mut indexes = Vec::new();
-
                     let query_map = &query_result.col_specs;
 
-                    for value in query_map {
-                        let (value_idx, _) =
-                            query_result.get_column_spec(value.name.as_str()).unwrap();
-
-                        indexes.push(value_idx);
-                    }
+                    let indexes: Vec<usize> = query_map
+                        .iter()
+                        .filter_map(|value| query_result.get_column_spec(value.name.as_str()))
+                        .map(|(value_idx, _)| value_idx)
+                        .collect();
 
-                    for row in query_result.rows.unwrap() {
+                    // ? Statements without rows (e.g an UPDATE sent through select) just return no rows
+                    for row in query_result.rows.unwrap_or_default() {
                         let mut row_vec: IndexMap<String, Value> = IndexMap::new();
 
                         for index in &indexes {
-                            let column = row.columns[index.to_owned()].as_ref();
+                            let column = row.columns.get(index.to_owned()).and_then(Option::as_ref);
                             let name = query_map[index.to_owned()].name.as_str().to_string();
 
                             let value = parse_cql_value(column);
@@ -96,62 +100,77 @@ pub async fn select(
                         result.push(row_vec);
                     }
 
-                    let mut query_result = Command {
-                        hash: "".to_string(),
-                        length: 0,
-                        command: "select".to_string(),
-                        keyspace: None,
-                        table: None,
-                        data: CommandData::SelectResponse(QueryResult {
+                    let rows = result.len();
+
+                    let stats = execution.timing.stats(raw_command, &execution.info, rows);
+
+                    let response_bytes = send_response_with_stats(
+                        &write,
+                        "select",
+                        CommandData::SelectResponse(QueryResult {
                             result,
                             error: None,
+                            warnings: query_result.warnings,
+                            execution: Some(execution.info),
+                            tracing: execution.tracing,
+                            stats: None,
                         }),
-                        nonce: raw_command.nonce.clone(), // todo: do not clone
-                    };
-
-                    let string_query_data = serde_json::to_string(&query_result.data).unwrap();
+                        raw_command.nonce.clone(),
+                        stats,
+                    )
+                    .await;
 
-                    query_result.length = string_query_data.len() + query_result.command.len();
+                    (response_bytes, rows)
+                }
+                Err(error) => {
+                    let error = Error::from(error);
 
-                    query_result.hash = calculate_hash(
-                        query_result.command.to_string()
-                            + &query_result.length.to_string()
-                            + &string_query_data,
-                    );
+                    metrics::record_error(error.code());
 
-                    let response = serde_json::to_string(&query_result).unwrap();
+                    let stats = execution.timing.stats(raw_command, &execution.info, 0);
 
-                    match write.lock().await.send(Message::Text(response)).await {
-                        _ => {}
-                    }
-                }
-                Err(error) => {
-                    let query_result = Command {
-                        hash: "".to_string(),
-                        length: 0,
-                        command: "select".to_string(),
-                        keyspace: None,
-                        table: None,
-                        data: CommandData::SelectResponse(QueryResult {
+                    let response_bytes = send_response_with_stats(
+                        &write,
+                        "select",
+                        CommandData::SelectResponse(QueryResult {
                             result: Vec::new(),
                             error: Some(error.to_string()),
+                            warnings: Vec::new(),
+                            execution: Some(execution.info),
+                            tracing: execution.tracing,
+                            stats: None,
                         }),
-                        nonce: raw_command.nonce.clone(),
-                    };
-
-                    let response = serde_json::to_string(&query_result).unwrap();
+                        raw_command.nonce.clone(),
+                        stats,
+                    )
+                    .await;
 
-                    match write.lock().await.send(Message::Text(response)).await {
-                        _ => {}
-                    }
+                    (response_bytes, 0)
                 }
-            }
+            };
+
+            slow_log::record(SlowQuery {
+                connection: &connection_id,
+                command: "select",
+                nonce: raw_command.nonce.as_deref(),
+                cql: &query.query,
+                values: &query.values,
+                rows,
+                response_bytes,
+                duration: execution.duration,
+            })
+            .await;
         }
 
         _ => {
-            if *LOGGING.lock().await {
-                println!("[Warn] A User sent an invalid command: {:?}", command);
-            }
+            warn!("A User sent an invalid command: {:?}", command);
+
+            send_failure(
+                &write,
+                &Error::InvalidData("Invalid data for select".to_string()),
+                raw_command.nonce.clone(),
+            )
+            .await;
         }
     }
 }
@@ -0,0 +1,72 @@
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+use crate::{
+    state,
+    structs::{
+        common::{Command, CommandData},
+        connect::ConnectResponse,
+    },
+    util::response::{send_response, Outgoing},
+};
+
+async fn send_result(write: &Outgoing, raw_command: &Command, error: Option<String>) {
+    let result = match error {
+        Some(_) => "Failed to switch keyspace",
+        None => "Switched keyspace",
+    };
+
+    send_response(
+        write,
+        "use",
+        CommandData::ConnectResponse(ConnectResponse {
+            result: result.to_string(),
+            error,
+            warnings: Vec::new(),
+        }),
+        raw_command.nonce.clone(),
+    )
+    .await;
+}
+
+// ? Changes the keyspace used by commands that don't send their own
+pub async fn use_keyspace(write: Outgoing, raw_command: &Command, user: Arc<Mutex<state::ClientState>>) {
+    let keyspace = match &raw_command.data {
+        CommandData::Use(use_data) => use_data.keyspace.clone(),
+        _ => {
+            send_result(&write, raw_command, Some("Invalid data for use".to_string())).await;
+
+            return;
+        }
+    };
+
+    let connection_lock = Arc::clone(&user.lock().await.connection_lock);
+    let _connection_guard = connection_lock.lock().await;
+
+    let snapshot = user.lock().await.session();
+
+    let session = match snapshot {
+        Some((session, _)) => session,
+        None => {
+            send_result(&write, raw_command, Some("Not connected to Scylla".to_string())).await;
+
+            return;
+        }
+    };
+
+    // ? The keyspace ends up in the query text, so we only take ones the cluster knows about
+    if !session
+        .session
+        .get_cluster_data()
+        .get_keyspace_info()
+        .contains_key(&keyspace)
+    {
+        send_result(&write, raw_command, Some(format!("Unknown keyspace: {}", keyspace))).await;
+
+        return;
+    }
+
+    user.lock().await.keyspace = keyspace;
+
+    send_result(&write, raw_command, None).await;
+}
@@ -0,0 +1,294 @@
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::error::{Error, Result};
+use crate::structs::connect::{ConnectData, ExecutionProfileOptions};
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Protocol {
+    WebSocket,
+    Framed, // ? 4 byte big endian length + payload over plain TCP
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json, // ? One JSON object per line, with the connection / command fields of every span
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ListenerConfig {
+    #[serde(default = "default_host")]
+    pub host: String,
+    #[serde(default = "default_port")]
+    pub port: u16,
+    #[serde(default = "default_protocol")]
+    pub protocol: Protocol,
+    pub path: Option<String>, // ? If this is set we listen on a unix socket at this path instead of host:port
+    pub permissions: Option<String>, // ? The permissions for the unix socket in octal, e.g "660"
+    pub tls: Option<TlsConfig>, // ? Only used for host:port listeners
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct TlsConfig {
+    pub cert: String, // ? PEM certificate chain
+    pub key: String, // ? PEM private key
+    pub ca: Option<String>, // ? If set, client certificates are verified against this CA (mTLS)
+    #[serde(rename = "requireClientCert", default)]
+    pub require_client_cert: bool,
+}
+
+// ? Serves /metrics, /healthz and /readyz, kept apart from the client listeners so it can be bound to a private address
+#[derive(Clone, Debug, Deserialize)]
+pub struct HttpConfig {
+    #[serde(default = "default_host")]
+    pub host: String,
+    pub port: u16,
+    pub canary: Option<String>, // ? A profile from profiles, /readyz is only ready once it can query the cluster
+    #[serde(rename = "canaryTimeout", default = "default_canary_timeout")]
+    pub canary_timeout: u64, // ? in milliseconds, for connecting and for the query
+}
+
+// ? Once the file would grow past maxBytes it's renamed to path.1 (path.1 to path.2 and so on), no maxBytes means it's never rotated
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct RotationConfig {
+    #[serde(rename = "maxBytes")]
+    pub max_bytes: Option<u64>,
+    #[serde(rename = "maxFiles", default = "default_max_files")]
+    pub max_files: usize, // ? how many rotated files are kept
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct SlowQueryLogConfig {
+    pub path: String, // ? NDJSON, one statement per line
+    #[serde(flatten)]
+    pub rotation: RotationConfig,
+    #[serde(default = "default_slow_query_threshold")]
+    pub threshold: u64, // ? in milliseconds
+    #[serde(rename = "redactValues", default = "default_redact_values")]
+    pub redact_values: bool, // ? only log the types of the bound values
+}
+
+// ? Every data modifying command (insert and raw INSERT / UPDATE / DELETE / BATCH / DDL) is written here
+#[derive(Clone, Debug, Deserialize)]
+pub struct AuditLogConfig {
+    pub path: String,
+    #[serde(flatten)]
+    pub rotation: RotationConfig,
+}
+
+// ? What happens on SIGINT / SIGTERM
+#[derive(Clone, Debug, Deserialize)]
+pub struct ShutdownConfig {
+    #[serde(default = "default_shutdown_timeout")]
+    pub timeout: u64, // ? in milliseconds, how long running commands get to finish
+    #[serde(rename = "retryAfter", default = "default_retry_after")]
+    pub retry_after: u64, // ? in milliseconds, sent to the clients with the shutdown notice
+}
+
+impl Default for ShutdownConfig {
+    fn default() -> Self {
+        Self {
+            timeout: default_shutdown_timeout(),
+            retry_after: default_retry_after(),
+        }
+    }
+}
+
+// ? A cluster clients can connect to by name, so the credentials never leave the server
+#[derive(Clone, Debug, Deserialize)]
+pub struct ProfileConfig {
+    #[serde(flatten)]
+    pub connect: ConnectData,
+    pub tokens: Option<Vec<String>>, // ? sha512 (hex) of the tokens allowed to use this profile
+    #[serde(default)]
+    pub public: bool, // ? Anyone can use the profile, it has to be set explicitly instead of just leaving out the tokens
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Config {
+    #[serde(default = "default_logging")]
+    pub logging: bool,
+    #[serde(rename = "logLevel")]
+    pub log_level: Option<String>, // ? Same syntax as RUST_LOG, e.g "info,scyllatcp::commands=debug"
+    #[serde(rename = "logFormat", default)]
+    pub log_format: LogFormat,
+    pub listeners: Vec<ListenerConfig>,
+    pub http: Option<HttpConfig>,
+    #[serde(rename = "slowQueryLog")]
+    pub slow_query_log: Option<SlowQueryLogConfig>,
+    #[serde(rename = "auditLog")]
+    pub audit_log: Option<AuditLogConfig>,
+    #[serde(default)]
+    pub profiles: HashMap<String, ProfileConfig>,
+    #[serde(rename = "allowClientCredentials", default = "default_allow_client_credentials")]
+    pub allow_client_credentials: bool, // ? if false clients can only connect with a profile
+    #[serde(rename = "adminTokens", default)]
+    pub admin_tokens: Vec<String>, // ? sha512 (hex) of the tokens allowed to run admin commands, none means admin commands are disabled
+    #[serde(default)]
+    pub shutdown: ShutdownConfig,
+    #[serde(rename = "executionProfiles", default)]
+    pub execution_profiles: BTreeMap<String, ExecutionProfileOptions>, // ? added to every session, profiles sent on connect win
+}
+
+fn default_host() -> String {
+    "127.0.0.1".to_string()
+}
+
+fn default_port() -> u16 {
+    8080
+}
+
+fn default_protocol() -> Protocol {
+    Protocol::WebSocket
+}
+
+fn default_logging() -> bool {
+    true
+}
+
+fn default_slow_query_threshold() -> u64 {
+    1000
+}
+
+fn default_canary_timeout() -> u64 {
+    2000
+}
+
+fn default_shutdown_timeout() -> u64 {
+    30000
+}
+
+fn default_retry_after() -> u64 {
+    5000
+}
+
+fn default_max_files() -> usize {
+    5
+}
+
+fn default_redact_values() -> bool {
+    true
+}
+
+fn default_allow_client_credentials() -> bool {
+    true
+}
+
+impl Config {
+    pub fn load(path: &str) -> Result<Self> {
+        let file = std::fs::read_to_string(path)
+            .map_err(|e| Error::Startup(format!("Failed to read the config file: {}", e)))?;
+
+        let config: Config = serde_json::from_str(&file)
+            .map_err(|e| Error::Startup(format!("Failed to parse the config file: {}", e)))?;
+
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    // ? What serde can't check for us
+    fn validate(&self) -> Result<()> {
+        for (name, profile) in self.profiles.iter() {
+            let has_tokens = profile.tokens.as_ref().is_some_and(|tokens| !tokens.is_empty());
+
+            match (profile.public, has_tokens) {
+                (false, false) => {
+                    return Err(Error::Startup(format!(
+                        "Profile {} has no tokens, add some or set \"public\": true to let anyone use it",
+                        name
+                    )));
+                }
+                (true, true) => {
+                    return Err(Error::Startup(format!("Profile {} is public, so it can't have tokens", name)));
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn path_from_args(args: &[String]) -> Option<&str> {
+        if args.len() > 2 && args[1] == "--config" {
+            return Some(&args[2]);
+        }
+
+        None
+    }
+
+    // ? either ./scylla --config config.json or the old ./scylla 127.0.0.1 8080 true
+    pub fn from_args(args: &[String]) -> Result<Self> {
+        if let Some(path) = Config::path_from_args(args) {
+            return Config::load(path);
+        }
+
+        let mut port = 8080;
+        let mut host = "127.0.0.1";
+        let mut logging = true;
+
+        if args.len() > 1 {
+            host = &args[1];
+        }
+
+        if args.len() > 2 {
+            port = args[2]
+                .parse::<u16>()
+                .map_err(|_| Error::Startup(format!("Invalid port: {}", args[2])))?;
+        }
+
+        if args.len() > 3 {
+            logging = args[3]
+                .parse::<bool>()
+                .map_err(|_| Error::Startup(format!("Invalid logging flag (true or false): {}", args[3])))?;
+        }
+
+        Ok(Self {
+            logging,
+            log_level: None,
+            log_format: LogFormat::Text,
+            listeners: vec![ListenerConfig {
+                host: host.to_string(),
+                port,
+                protocol: Protocol::WebSocket,
+                path: None,
+                permissions: None,
+                tls: None,
+            }],
+            http: None,
+            slow_query_log: None,
+            audit_log: None,
+            profiles: HashMap::new(),
+            allow_client_credentials: true,
+            admin_tokens: Vec::new(),
+            shutdown: ShutdownConfig::default(),
+            execution_profiles: BTreeMap::new(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Config;
+
+    fn config(profile: &str) -> Config {
+        serde_json::from_str(&format!(
+            r#"{{"listeners": [], "profiles": {{"main": {{"contactPoints": ["10.0.0.1:9042"], "localDataCenter": "dc1", "credentials": {{"username": "a", "password": "b"}}, "keyspace": "ks"{}}}}}}}"#,
+            profile
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn profiles_need_tokens_or_public() {
+        assert!(config("").validate().is_err());
+        assert!(config(r#", "tokens": []"#).validate().is_err());
+        assert!(config(r#", "tokens": ["abc"], "public": true"#).validate().is_err());
+        assert!(config(r#", "tokens": ["abc"]"#).validate().is_ok());
+        assert!(config(r#", "public": true"#).validate().is_ok());
+    }
+}
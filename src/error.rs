@@ -0,0 +1,159 @@
+use scylla::transport::errors::QueryError;
+use std::fmt;
+use tokio_tungstenite::tungstenite;
+
+use crate::metrics;
+
+// ? Everything that can go wrong in the server, code() is what we send back to the client and count in the metrics
+#[derive(Debug)]
+pub enum Error {
+    Startup(String), // ? The config is wrong or something it points at can't be used, the server doesn't start
+    Io(std::io::Error),
+    WebSocket(Box<tungstenite::Error>), // ? Boxed, it's by far the largest variant
+    Serde(String), // ? A frame that isn't a valid command or a response we couldn't encode
+    Driver(QueryError),
+    InvalidData(String), // ? The command parsed, but can't be run as it is
+    InvalidStatement(String), // ? The statement options (execution profile, retry policy) don't exist
+    Forbidden(String), // ? A token that doesn't match, or something only the server config may do
+    ConnectFailed(String), // ? Building a session with valid settings didn't work out
+    Tls(String),
+    Timeout(String), // ? Our own timeouts, the driver's end up in Driver
+    NotConnected,
+    AlreadyConnected,
+    UnknownCommand(String),
+    Cancelled, // ? By an admin, or because the server shut down
+    Kicked,
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::Startup(_) => "startup",
+            Error::Io(_) => "io_error",
+            Error::WebSocket(_) => "websocket",
+            Error::Serde(_) => "parse_error",
+            Error::Driver(e) => metrics::error_code(e),
+            Error::InvalidData(_) => "invalid_data",
+            Error::InvalidStatement(_) => "invalid_statement",
+            Error::Forbidden(_) => "forbidden",
+            Error::ConnectFailed(_) => "connect_failed",
+            Error::Tls(_) => "tls_error",
+            Error::Timeout(_) => "client_timeout",
+            Error::NotConnected => "not_connected",
+            Error::AlreadyConnected => "already_connected",
+            Error::UnknownCommand(_) => "unknown_command",
+            Error::Cancelled => "cancelled",
+            Error::Kicked => "kicked",
+        }
+    }
+
+    // ? A client going away without a close frame is how most connections end, it's not worth a warning
+    pub fn is_disconnect(&self) -> bool {
+        matches!(
+            self,
+            Error::WebSocket(e) if matches!(
+                **e,
+                tungstenite::Error::ConnectionClosed
+                    | tungstenite::Error::AlreadyClosed
+                    | tungstenite::Error::Protocol(tungstenite::error::ProtocolError::ResetWithoutClosingHandshake)
+            )
+        ) || matches!(
+            self,
+            Error::Io(e) if matches!(
+                e.kind(),
+                std::io::ErrorKind::ConnectionReset | std::io::ErrorKind::BrokenPipe | std::io::ErrorKind::UnexpectedEof
+            )
+        )
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Startup(e) => write!(f, "{}", e),
+            Error::Io(e) => write!(f, "IO error: {}", e),
+            Error::WebSocket(e) => write!(f, "WebSocket error: {}", e),
+            Error::Serde(e) => write!(f, "Error: {}", e),
+            Error::Driver(e) => write!(f, "{}", e),
+            Error::InvalidData(e) => write!(f, "{}", e),
+            Error::InvalidStatement(e) => write!(f, "{}", e),
+            Error::Forbidden(e) => write!(f, "{}", e),
+            Error::ConnectFailed(e) => write!(f, "{}", e),
+            Error::Tls(e) => write!(f, "TLS error: {}", e),
+            Error::Timeout(e) => write!(f, "{}", e),
+            Error::NotConnected => write!(f, "Not connected to Scylla"),
+            Error::AlreadyConnected => write!(f, "Already connected to scylla"),
+            Error::UnknownCommand(command) => write!(f, "Unknown command: {}", command),
+            Error::Cancelled => write!(f, "The command was cancelled"),
+            Error::Kicked => write!(f, "Disconnected by an admin"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+// ? The framed transport hands us its IO errors wrapped in a tungstenite error, so they are unwrapped here
+impl From<tungstenite::Error> for Error {
+    fn from(e: tungstenite::Error) -> Self {
+        match e {
+            tungstenite::Error::Io(e) => Error::Io(e),
+            e => Error::WebSocket(Box::new(e)),
+        }
+    }
+}
+
+impl From<openssl::error::ErrorStack> for Error {
+    fn from(e: openssl::error::ErrorStack) -> Self {
+        Error::Tls(e.to_string())
+    }
+}
+
+impl From<openssl::ssl::Error> for Error {
+    fn from(e: openssl::ssl::Error) -> Self {
+        Error::Tls(e.to_string())
+    }
+}
+
+impl From<QueryError> for Error {
+    fn from(e: QueryError) -> Self {
+        Error::Driver(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Serde(e.to_string())
+    }
+}
+
+impl From<rmp_serde::decode::Error> for Error {
+    fn from(e: rmp_serde::decode::Error) -> Self {
+        Error::Serde(e.to_string())
+    }
+}
+
+impl From<rmp_serde::encode::Error> for Error {
+    fn from(e: rmp_serde::encode::Error) -> Self {
+        Error::Serde(e.to_string())
+    }
+}
+
+impl From<ciborium::de::Error<std::io::Error>> for Error {
+    fn from(e: ciborium::de::Error<std::io::Error>) -> Self {
+        Error::Serde(e.to_string())
+    }
+}
+
+impl From<ciborium::ser::Error<std::io::Error>> for Error {
+    fn from(e: ciborium::ser::Error<std::io::Error>) -> Self {
+        Error::Serde(e.to_string())
+    }
+}
@@ -0,0 +1,181 @@
+use serde::Serialize;
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, OnceLock,
+    },
+    time::{Duration, Instant},
+};
+use tokio::sync::Mutex;
+use tracing::warn;
+
+use crate::{
+    config::{Config, HttpConfig},
+    error::{self, Error},
+    state::Store,
+    structs::connect::ConnectData,
+    util::session::{build_session, ScyllaSession},
+};
+
+// ? Set once every listener from the config is bound
+static LISTENING: AtomicBool = AtomicBool::new(false);
+
+static CANARY: OnceLock<Canary> = OnceLock::new();
+
+// ? The canary has its own session so it never shares (or keeps alive) a client's session
+struct Canary {
+    connect_data: ConnectData,
+    timeout: Duration,
+    session: Mutex<Option<ScyllaSession>>, // ? Built on the first /readyz, and again after a failed query
+}
+
+#[derive(Serialize)]
+pub struct Readiness {
+    pub ready: bool,
+    pub listening: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub canary: Option<CanaryStatus>,
+    pub sessions: Vec<SessionHealth>,
+}
+
+#[derive(Serialize)]
+pub struct CanaryStatus {
+    pub ok: bool,
+    #[serde(rename = "latencyMs")]
+    pub latency_ms: f64,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct SessionHealth {
+    pub session: String, // ? "canary" or the start of the session key, same as the metrics label
+    pub nodes: Vec<NodeHealth>,
+}
+
+#[derive(Serialize)]
+pub struct NodeHealth {
+    pub address: String,
+    pub datacenter: Option<String>,
+    pub rack: Option<String>,
+    pub up: bool,
+    pub enabled: bool, // ? false for nodes the load balancing policy never connects to
+}
+
+pub fn init(config: &Config) -> error::Result<()> {
+    let (http, name) = match &config.http {
+        Some(http @ HttpConfig { canary: Some(name), .. }) => (http, name),
+        _ => return Ok(()),
+    };
+
+    let profile = config
+        .profiles
+        .get(name)
+        .ok_or_else(|| Error::Startup(format!("Failed to set up the canary: unknown profile {}", name)))?;
+
+    let _ = CANARY.set(Canary {
+        connect_data: profile.connect.clone(),
+        timeout: Duration::from_millis(http.canary_timeout),
+        session: Mutex::new(None),
+    });
+
+    Ok(())
+}
+
+pub fn set_listening(listening: bool) {
+    LISTENING.store(listening, Ordering::SeqCst);
+}
+
+pub async fn readiness(users: &Arc<Mutex<Store>>) -> Readiness {
+    let listening = LISTENING.load(Ordering::SeqCst);
+    let mut sessions = Vec::new();
+
+    let canary = match CANARY.get() {
+        Some(canary) => {
+            let mut session = canary.session.lock().await;
+            let status = canary.check(&mut session).await;
+
+            if let Some(session) = session.as_ref() {
+                sessions.push(session_health("canary".to_string(), session));
+            }
+
+            Some(status)
+        }
+        None => None,
+    };
+
+    let shared = users
+        .lock()
+        .await
+        .sessions
+        .iter()
+        .map(|(key, shared)| (key.chars().take(12).collect::<String>(), Arc::clone(&shared.session)))
+        .collect::<Vec<_>>();
+
+    for (key, session) in shared {
+        sessions.push(session_health(key, &session));
+    }
+
+    Readiness {
+        ready: listening && canary.as_ref().is_none_or(|canary| canary.ok),
+        listening,
+        canary,
+        sessions,
+    }
+}
+
+impl Canary {
+    async fn check(&self, session: &mut Option<ScyllaSession>) -> CanaryStatus {
+        let started = Instant::now();
+
+        let result = self.query(session).await;
+
+        if let Err(error) = &result {
+            warn!("The canary query failed: {}", error);
+
+            // ? Dropping the session means the next check starts from scratch, e.g with a fresh contact point lookup
+            *session = None;
+        }
+
+        CanaryStatus {
+            ok: result.is_ok(),
+            latency_ms: started.elapsed().as_secs_f64() * 1000.0,
+            error: result.err().map(|error| error.to_string()),
+        }
+    }
+
+    async fn query(&self, session: &mut Option<ScyllaSession>) -> error::Result<()> {
+        if session.is_none() {
+            let built = tokio::time::timeout(self.timeout, build_session(&self.connect_data))
+                .await
+                .map_err(|_| Error::Timeout("Timed out connecting".to_string()))??;
+
+            *session = Some(built);
+        }
+
+        let session = session.as_ref().ok_or(Error::NotConnected)?;
+
+        tokio::time::timeout(self.timeout, session.session.query("SELECT now() FROM system.local", &[]))
+            .await
+            .map_err(|_| Error::Timeout("Timed out running the query".to_string()))??;
+
+        Ok(())
+    }
+}
+
+fn session_health(session: String, scylla_session: &ScyllaSession) -> SessionHealth {
+    let nodes = scylla_session
+        .session
+        .get_cluster_data()
+        .get_nodes_info()
+        .iter()
+        .map(|node| NodeHealth {
+            address: node.address.to_string(),
+            datacenter: node.datacenter.clone(),
+            rack: node.rack.clone(),
+            up: !node.is_down(),
+            enabled: node.is_enabled(),
+        })
+        .collect();
+
+    SessionHealth { session, nodes }
+}
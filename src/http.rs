@@ -0,0 +1,102 @@
+use std::{sync::Arc, time::Duration};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader, Take},
+    net::{TcpListener, TcpStream},
+    sync::Mutex,
+};
+use tracing::debug;
+
+use crate::{health, metrics, state::Store};
+
+// ? Just enough HTTP for a scraper / health checks, every request gets one response and the connection is closed
+pub async fn serve(listener: TcpListener, users: Arc<Mutex<Store>>) {
+    while let Ok((stream, _)) = listener.accept().await {
+        tokio::spawn(handle_request(stream, Arc::clone(&users)));
+    }
+}
+
+// ? The request line and the headers together, anything bigger is answered with a 431
+const MAX_HEAD: u64 = 8192;
+
+const BAD_REQUEST: &str = "400 Bad Request";
+const TOO_LARGE: &str = "431 Request Header Fields Too Large";
+
+// ? Err is the status to answer with
+async fn read_path(stream: &mut TcpStream) -> Result<String, &'static str> {
+    let mut reader = BufReader::new(stream.take(MAX_HEAD));
+    let mut request_line = String::new();
+
+    read_head_line(&mut reader, &mut request_line).await?;
+
+    // ? We don't need any of the headers, but they have to be read before we answer
+    loop {
+        let mut header = String::new();
+
+        read_head_line(&mut reader, &mut header).await?;
+
+        if header.trim().is_empty() {
+            break;
+        }
+    }
+
+    let mut parts = request_line.split_whitespace();
+
+    if parts.next() != Some("GET") {
+        return Err(BAD_REQUEST);
+    }
+
+    let path = parts.next().ok_or(BAD_REQUEST)?;
+
+    Ok(path.split('?').next().unwrap_or(path).to_string())
+}
+
+async fn read_head_line(reader: &mut BufReader<Take<&mut TcpStream>>, line: &mut String) -> Result<(), &'static str> {
+    match reader.read_line(line).await {
+        Ok(_) if line.ends_with('\n') => Ok(()),
+        // ? The line was cut off by the cap, not by the client closing the connection
+        Ok(_) if reader.get_ref().limit() == 0 => Err(TOO_LARGE),
+        _ => Err(BAD_REQUEST),
+    }
+}
+
+async fn handle_request(mut stream: TcpStream, users: Arc<Mutex<Store>>) {
+    let path = match tokio::time::timeout(Duration::from_secs(5), read_path(&mut stream)).await {
+        Ok(path) => path,
+        Err(_) => return,
+    };
+
+    debug!(path = path.as_deref().unwrap_or_default(), "HTTP request");
+
+    let (status, content_type, body) = match path.as_deref() {
+        Ok("/metrics") => (
+            "200 OK",
+            prometheus::TEXT_FORMAT,
+            metrics::render(&users).await,
+        ),
+        // ? Only says the process is up and serving, readiness is what says if it should get traffic
+        Ok("/healthz") => ("200 OK", "application/json", "{\"status\":\"ok\"}".to_string()),
+        Ok("/readyz") => {
+            let readiness = health::readiness(&users).await;
+            let status = if readiness.ready { "200 OK" } else { "503 Service Unavailable" };
+
+            (
+                status,
+                "application/json",
+                serde_json::to_string(&readiness).unwrap_or_default(),
+            )
+        }
+        Ok(_) => ("404 Not Found", "text/plain", "Not found\n".to_string()),
+        Err(status) => (*status, "text/plain", format!("{}\n", status)),
+    };
+
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+
+    let _ = stream.write_all(response.as_bytes()).await;
+    let _ = stream.shutdown().await;
+}
@@ -0,0 +1,80 @@
+use std::{io::IsTerminal, sync::OnceLock};
+use tracing_subscriber::{
+    fmt, layer::SubscriberExt, reload, util::SubscriberInitExt, EnvFilter, Registry,
+};
+
+use crate::{
+    config::{Config, LogFormat},
+    error::{self, Error},
+};
+
+// ? Kept around so the level can be changed while the server is running
+static FILTER: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
+
+// ? logLevel from the config wins over RUST_LOG, "logging": false turns everything off
+pub fn directives(config: &Config) -> String {
+    if !config.logging {
+        return "off".to_string();
+    }
+
+    config
+        .log_level
+        .clone()
+        .or_else(|| std::env::var("RUST_LOG").ok())
+        .unwrap_or_else(|| "info".to_string())
+}
+
+pub fn init(config: &Config) {
+    let filter = EnvFilter::try_new(directives(config)).unwrap_or_else(|_| EnvFilter::new("info"));
+    let (filter, handle) = reload::Layer::new(filter);
+    let registry = tracing_subscriber::registry().with(filter);
+
+    match config.log_format {
+        LogFormat::Text => registry
+            .with(fmt::layer().with_ansi(std::io::stdout().is_terminal()))
+            .init(),
+        LogFormat::Json => registry
+            .with(fmt::layer().json().with_current_span(true).with_span_list(true))
+            .init(),
+    }
+
+    let _ = FILTER.set(handle);
+}
+
+// ? Takes the same directives as RUST_LOG, e.g "info" or "warn,scyllatcp::commands=debug"
+pub fn set_level(directives: &str) -> error::Result<()> {
+    let filter = EnvFilter::try_new(directives).map_err(|e| Error::InvalidData(e.to_string()))?;
+
+    FILTER
+        .get()
+        .ok_or_else(|| Error::InvalidData("Logging is not initialized".to_string()))?
+        .reload(filter)
+        .map_err(|e| Error::InvalidData(e.to_string()))
+}
+
+// ? On SIGHUP the config file is read again and its log level is applied, nothing else is reloaded
+#[cfg(unix)]
+pub async fn reload_on_sighup(path: String) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => hangup,
+        Err(e) => {
+            tracing::warn!("Failed to listen for SIGHUP: {}", e);
+
+            return;
+        }
+    };
+
+    while hangup.recv().await.is_some() {
+        let result = Config::load(&path).and_then(|config| set_level(&directives(&config)));
+
+        match result {
+            Ok(()) => tracing::info!(path = %path, "Reloaded the log level"),
+            Err(e) => tracing::warn!(path = %path, "Failed to reload the log level: {}", e),
+        }
+    }
+}
+
+#[cfg(not(unix))]
+pub async fn reload_on_sighup(_: String) {}
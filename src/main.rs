@@ -1,176 +1,634 @@
-use futures_util::stream::SplitSink;
-use futures_util::{SinkExt, StreamExt};
-use lazy_static::lazy_static;
+use futures_util::future::{AbortHandle, Abortable};
+use futures_util::StreamExt;
 use std::env;
 use std::net::SocketAddr;
 use std::sync::Arc;
-use tokio::net::{TcpListener, TcpStream};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpListener;
+#[cfg(unix)]
+use tokio::net::UnixListener;
 use tokio::sync::Mutex;
 use tokio_tungstenite::tungstenite::protocol::Message;
-use tokio_tungstenite::WebSocketStream;
+use tokio_util::task::TaskTracker;
+use tracing::{error_span, info, warn, Instrument};
 
-use crate::calculate_hash::calculate_hash;
+use openssl::ssl::SslAcceptor;
+
+use crate::config::Protocol;
 use crate::structs::common::Command;
+use crate::transport::Transport;
+use crate::util::encoding::Encoding;
+use crate::error::Error;
+use crate::structs::common::CommandData;
+use crate::structs::shutdown::ShutdownNotice;
+use crate::util::response::{close, send_failure, send_response, Outgoing, Writer};
 
 mod calculate_hash;
 mod commands;
+mod config;
+mod error;
+mod health;
+mod http;
+mod logging;
+mod metrics;
+mod shutdown;
 mod state;
 mod structs;
+mod tls;
+mod transport;
 mod util;
 
-// static item called "logging" which is a bool
+#[tokio::main]
+async fn main() {
+    let args: Vec<String> = env::args().collect();
 
-// static mut LOGGING: bool = true;
+    // ? Logging might not be set up yet, so this goes straight to stderr
+    if let Err(e) = run(&args).await {
+        eprintln!("Failed to start: {}", e);
 
-lazy_static! {
-    static ref LOGGING: Mutex<bool> = Mutex::new(true);
+        std::process::exit(1);
+    }
 }
 
-#[tokio::main]
-async fn main() {
-    let args: Vec<String> = env::args().collect();
+async fn run(args: &[String]) -> error::Result<()> {
+    let config = config::Config::from_args(args)?;
+
+    logging::init(&config);
+    metrics::init();
+
+    if let Some(slow_query_log) = &config.slow_query_log {
+        util::slow_log::init(slow_query_log)
+            .map_err(|e| Error::Startup(format!("Failed to open the slow query log: {}", e)))?;
+    }
+
+    if let Some(audit_log) = &config.audit_log {
+        util::audit::init(audit_log).map_err(|e| Error::Startup(format!("Failed to open the audit log: {}", e)))?;
+    }
+
+    health::init(&config)?;
+
+    if let Some(path) = config::Config::path_from_args(args) {
+        tokio::spawn(logging::reload_on_sighup(path.to_string()));
+    }
 
-    let mut port = 8080;
-    let mut host = "127.0.0.1";
+    let config = Arc::new(config);
+    let users = Arc::new(Mutex::new(state::Store::new(Arc::clone(&config))));
 
-    if args.len() > 1 {
-        host = &args[1];
+    let mut listeners = Vec::new();
+
+    for listener_config in config.listeners.iter() {
+        if let Some(path) = &listener_config.path {
+            listeners.push(bind_unix(path, listener_config, Arc::clone(&users)).await?);
+
+            continue;
+        }
+
+        let addr = format!("{}:{}", listener_config.host, listener_config.port);
+        let addr = addr
+            .parse::<SocketAddr>()
+            .map_err(|e| Error::Startup(format!("Invalid listener address {}: {}", addr, e)))?;
+        let listener = TcpListener::bind(&addr)
+            .await
+            .map_err(|e| Error::Startup(format!("Failed to bind to {}: {}", addr, e)))?;
+
+        let tls = match &listener_config.tls {
+            Some(tls_config) => Some(Arc::new(tls::build_acceptor(tls_config)?)),
+            None => None,
+        };
+
+        info!(
+            %addr,
+            protocol = ?listener_config.protocol,
+            tls = tls.is_some(),
+            "Server listening"
+        );
+
+        listeners.push(tokio::spawn(accept_loop(
+            listener,
+            listener_config.protocol,
+            tls,
+            Arc::clone(&users),
+        )));
+    }
+
+    health::set_listening(true);
+
+    if let Some(http_config) = &config.http {
+        let addr = format!("{}:{}", http_config.host, http_config.port);
+        let listener = TcpListener::bind(&addr)
+            .await
+            .map_err(|e| Error::Startup(format!("Failed to bind the HTTP listener to {}: {}", addr, e)))?;
+
+        info!(%addr, "HTTP listening");
+
+        // ? Not one of the listeners we wait for, /readyz keeps answering while the connections drain
+        tokio::spawn(http::serve(listener, Arc::clone(&users)));
+    }
+
+    shutdown::signal().await;
+
+    info!("Shutting down");
+
+    // ? /readyz stops reporting ready right away, so no new traffic is sent our way
+    health::set_listening(false);
+
+    let deadline = shutdown::begin(std::time::Duration::from_millis(config.shutdown.timeout));
+
+    for listener in listeners {
+        let _ = listener.await;
     }
 
-    if args.len() > 2 {
-        port = args[2].parse::<u16>().unwrap();
+    // ? A second past the deadline, so the cancelled commands can still send their error
+    let closed = tokio::time::timeout_at(
+        deadline + std::time::Duration::from_secs(1),
+        shutdown::connections().wait(),
+    )
+    .await;
+
+    if closed.is_err() {
+        warn!("Some connections didn't close in time, exiting anyway");
     }
 
-    if args.len() > 3 {
-        let mut logging = LOGGING.lock().await;
+    // ? The sessions are normally released by the connections, this also drops the ones of connections that didn't close
+    let mut store = users.lock().await;
 
-        *logging = args[3].parse::<bool>().unwrap();
+    store.clients.clear();
+    store.sessions.clear();
+
+    info!("Shut down");
+
+    Ok(())
+}
+
+async fn accept_loop(
+    listener: TcpListener,
+    protocol: Protocol,
+    tls: Option<Arc<SslAcceptor>>,
+    users: Arc<Mutex<state::Store>>,
+) {
+    // ? Once the shutdown starts the listener is dropped, so new connections are refused
+    while let Some(Ok((stream, ip))) = shutdown::token().run_until_cancelled(listener.accept()).await {
+        let users = Arc::clone(&users);
+
+        match &tls {
+            Some(acceptor) => {
+                let acceptor = Arc::clone(acceptor);
+
+                shutdown::connections().spawn(async move {
+                    match tls::accept(&acceptor, stream).await {
+                        Ok((stream, identity)) => {
+                            handle_stream(stream, ip.to_string(), identity, protocol, users).await;
+                        }
+                        Err(e) => {
+                            warn!(peer = %ip, "TLS handshake failed: {}", e);
+                        }
+                    }
+                });
+            }
+            None => {
+                shutdown::connections().spawn(handle_stream(stream, ip.to_string(), None, protocol, users));
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+async fn bind_unix(
+    path: &str,
+    listener_config: &config::ListenerConfig,
+    users: Arc<Mutex<state::Store>>,
+) -> error::Result<tokio::task::JoinHandle<()>> {
+    use std::os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt};
+
+    let startup = |message: &'static str| move |e: std::io::Error| Error::Startup(format!("{} {}: {}", message, path, e));
+
+    let mode = match &listener_config.permissions {
+        Some(permissions) => Some(
+            u32::from_str_radix(permissions, 8)
+                .map_err(|_| Error::Startup(format!("Invalid unix socket permissions: {}", permissions)))?,
+        ),
+        None => None,
+    };
+
+    // ? A socket file left over from a previous run would make the bind fail, anything that isn't a socket is left alone
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => {
+            std::fs::remove_file(path).map_err(startup("Failed to remove the old unix socket"))?;
+        }
+        Ok(_) => {
+            return Err(Error::Startup(format!("{} already exists and is not a unix socket", path)));
+        }
+        Err(_) => {}
     }
 
-    // so you would do ./scylla 127.0.0.1 8080
+    let listener = match mode {
+        // ? Bound in a directory only we can enter and moved into place once it has its permissions, so nobody can
+        // ? connect while the socket still has the default ones
+        Some(mode) => {
+            let socket_path = std::path::Path::new(path);
+            let private_dir = socket_path.with_file_name(format!(".scyllatcp-{}", std::process::id()));
+            let private_path = private_dir.join("socket");
+
+            let _ = std::fs::remove_dir_all(&private_dir);
+
+            std::fs::DirBuilder::new()
+                .mode(0o700)
+                .create(&private_dir)
+                .map_err(startup("Failed to create a directory next to the unix socket"))?;
 
-    let addr = format!("{}:{}", host, port).parse::<SocketAddr>().unwrap();
-    let listener = TcpListener::bind(&addr)
-        .await
-        .expect("Failed to bind to address");
+            let bound = UnixListener::bind(&private_path)
+                .map_err(startup("Failed to bind to unix socket"))
+                .and_then(|listener| {
+                    std::fs::set_permissions(&private_path, std::fs::Permissions::from_mode(mode))
+                        .map_err(startup("Failed to set the permissions of the unix socket"))?;
+                    std::fs::rename(&private_path, socket_path).map_err(startup("Failed to move the unix socket to"))?;
 
-    if *LOGGING.lock().await {
-        println!("[Info] Server listening on: {}", addr);
+                    Ok(listener)
+                });
+
+            let _ = std::fs::remove_dir_all(&private_dir);
+
+            bound?
+        }
+        None => UnixListener::bind(path).map_err(startup("Failed to bind to unix socket"))?,
+    };
+
+    info!(
+        addr = %format!("unix:{}", path),
+        protocol = ?listener_config.protocol,
+        "Server listening"
+    );
+
+    Ok(tokio::spawn(accept_unix_loop(
+        listener,
+        path.to_string(),
+        listener_config.protocol,
+        users,
+    )))
+}
+
+#[cfg(not(unix))]
+async fn bind_unix(
+    _: &str,
+    _: &config::ListenerConfig,
+    _: Arc<Mutex<state::Store>>,
+) -> error::Result<tokio::task::JoinHandle<()>> {
+    Err(Error::Startup("Unix sockets are not supported on this platform".to_string()))
+}
+
+#[cfg(unix)]
+async fn accept_unix_loop(
+    listener: UnixListener,
+    path: String,
+    protocol: Protocol,
+    users: Arc<Mutex<state::Store>>,
+) {
+    while let Some(Ok((stream, _))) = shutdown::token().run_until_cancelled(listener.accept()).await {
+        // ? unix peers don't have an address, so we use the socket path instead
+        shutdown::connections().spawn(handle_stream(
+            stream,
+            format!("unix:{}", path),
+            None,
+            protocol,
+            Arc::clone(&users),
+        ));
     }
 
-    let users = Arc::new(Mutex::new(state::Store::new()));
+    // ? The listener is closed once we get here, so the socket file would only point clients at nothing
+    drop(listener);
 
-    while let Ok((stream, _)) = listener.accept().await {
-        // let stream = Arc::new(Mutex::new(stream));
-        tokio::spawn(handle_connection(stream, Arc::clone(&users)));
+    if let Err(e) = remove_unix_socket(&path) {
+        warn!("Failed to remove the unix socket {}: {}", path, e);
     }
 }
 
-async fn handle_connection(raw_stream: TcpStream, users: Arc<Mutex<state::Store>>) {
-    let ws_stream = tokio_tungstenite::accept_async(raw_stream)
-        .await
-        .expect("Error during the websocket handshake occurred");
+#[cfg(unix)]
+fn remove_unix_socket(path: &str) -> std::io::Result<()> {
+    use std::os::unix::fs::FileTypeExt;
+
+    // ? Same as when binding, a file that isn't a socket is never removed
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => std::fs::remove_file(path),
+        _ => Ok(()),
+    }
+}
+
+async fn handle_stream<S>(
+    stream: S,
+    ip: String,
+    identity: Option<state::ClientIdentity>,
+    protocol: Protocol,
+    users: Arc<Mutex<state::Store>>,
+) where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let transport = match protocol {
+        Protocol::WebSocket => match transport::accept_websocket(stream).await {
+            Ok(transport) => transport,
+            Err(e) => {
+                warn!(peer = %ip, "WebSocket handshake failed: {}", Error::from(e));
+
+                return;
+            }
+        },
+        Protocol::Framed => transport::accept_framed(stream),
+    };
 
-    let ip = ws_stream.get_ref().peer_addr().unwrap();
-    let user = Arc::new(Mutex::new(state::ClientState::new(false, "test", None)));
     let rnd_id = rand::random::<u32>().to_string();
+    // ? The spans are at error level so the connection id / nonce are still attached when only warnings are logged
+    let span = error_span!("connection", id = %rnd_id);
+
+    handle_connection(transport, rnd_id, ip, identity, users)
+        .instrument(span)
+        .await;
+}
 
-    let (outgoing, incoming) = ws_stream.split();
+async fn handle_connection(
+    transport: Transport,
+    rnd_id: String,
+    ip: String,
+    identity: Option<state::ClientIdentity>,
+    users: Arc<Mutex<state::Store>>,
+) {
+    let encoding = transport.encoding;
+    let user = Arc::new(Mutex::new(state::ClientState::new(false, "test", None)));
+    let outgoing = Arc::new(Writer::new(transport.sink, encoding));
+    let incoming = Arc::new(Mutex::new(transport.stream));
+
+    match &identity {
+        Some(identity) => info!(
+            peer = %ip,
+            encoding = encoding.name(),
+            common_name = identity.common_name.as_deref().unwrap_or("unknown"),
+            fingerprint = %identity.fingerprint,
+            "New connection"
+        ),
+        None => info!(peer = %ip, encoding = encoding.name(), "New connection"),
+    }
 
-    let outgoing = Arc::new(Mutex::new(outgoing));
-    let incoming = Arc::new(Mutex::new(incoming));
+    {
+        let mut uu = user.lock().await;
 
-    if *LOGGING.lock().await {
-        println!("[Info] New connection from: {} with id: {}", ip, rnd_id);
+        uu.id = rnd_id.clone();
+        uu.peer = ip.clone();
+        uu.identity = identity;
     }
 
-    users
-        .lock()
-        .await
-        .clients
-        .insert(rnd_id.clone(), Arc::clone(&user));
+    {
+        let mut store = users.lock().await;
+
+        store.clients.insert(rnd_id.clone(), Arc::clone(&user));
+
+        metrics::CONNECTIONS.set(store.clients.len() as i64);
+    }
 
     let mut incoming = incoming.lock().await;
+    let kick = Arc::clone(&user.lock().await.kick);
+    // ? Every command of the connection runs on this, so the shutdown can wait for them without polling
+    let commands = TaskTracker::new();
+    let retry_after = users.lock().await.config.shutdown.retry_after;
 
-    while let Some(msg) = incoming.next().await {
-        let msg = msg.unwrap();
-
-        match msg {
-            Message::Text(text) => match serde_json::from_str::<Command>(&text) {
-                Ok(command) => {
-                    let hash = calculate_hash(
-                        command.command.to_string()
-                            + &command.length.to_string()
-                            + &serde_json::to_string(&command.data).unwrap(),
-                    );
-
-                    // if hash != command.hash {
-                    //     if *LOGGING.lock().await {
-                    //         println!("[Warn] Hashes do not match, dropping command");
-
-                    //         println!("Received hash: {}", command.hash);
-                    //         println!("Calculated hash: {}", hash);
-
-                    //         println!("Command: {}", command.command);
-                    //         println!("Length: {}", command.length);
-                    //         println!("Data: {}", serde_json::to_string(&command).unwrap());
-                    //     }
-
-                    //     outgoing
-                    //         .lock()
-                    //         .await
-                    //         .send(Message::Text(
-                    //             "Hashes do not match, dropping command".to_string(),
-                    //         ))
-                    //         .await
-                    //         .unwrap();
-
-                    //     continue;
-                    // }
-
-                    let feature = handle_command(Arc::clone(&outgoing), command, Arc::clone(&user));
-
-                    tokio::spawn(feature);
-                }
-                Err(e) => {
-                    let response = format!("Error: {}", e);
+    loop {
+        let msg = tokio::select! {
+            msg = incoming.next() => msg,
+            _ = kick.notified() => {
+                send_failure(&outgoing, &Error::Kicked, None).await;
 
-                    if *LOGGING.lock().await {
-                        println!("[Warn] A User sent an invalid command: {}", text);
-                    }
+                close(&outgoing).await;
+
+                break;
+            }
+            _ = shutdown::token().cancelled() => {
+                let deadline = shutdown::deadline().saturating_duration_since(tokio::time::Instant::now());
+
+                send_response(
+                    &outgoing,
+                    "shutdown",
+                    CommandData::Shutdown(ShutdownNotice {
+                        retry_after,
+                        deadline: deadline.as_millis() as u64,
+                    }),
+                    None,
+                )
+                .await;
+
+                drain(&user, &commands).await;
+
+                close(&outgoing).await;
+
+                break;
+            }
+        };
+
+        let msg = match msg {
+            Some(Ok(msg)) => msg,
+            Some(Err(e)) => {
+                let error = Error::from(e);
 
-                    outgoing
-                        .lock()
-                        .await
-                        .send(Message::Text(response))
-                        .await
-                        .unwrap();
+                if error.is_disconnect() {
+                    info!("User disconnected without closing: {}", error);
+                } else {
+                    warn!("Dropping the connection: {}", error);
                 }
-            },
+
+                break;
+            }
+            None => break,
+        };
+
+        let received_at = std::time::Instant::now();
+
+        metrics::BYTES_RECEIVED.inc_by(msg.len() as u64);
+
+        // ? Text frames are always JSON, binary frames use whatever encoding the client picked
+        let (decoded, data, frame_encoding) = match msg {
+            Message::Text(text) => (
+                Encoding::Json.decode(text.as_bytes()),
+                text.into_bytes(),
+                Encoding::Json,
+            ),
+            Message::Binary(data) => {
+                let encoding = *outgoing.encoding.lock().await;
+
+                (encoding.decode(&data), data, encoding)
+            }
             Message::Close(_) => {
-                if *LOGGING.lock().await {
-                    println!("[Info] User {} disconnected", rnd_id);
-                }
+                info!("User disconnected");
 
-                users.lock().await.clients.remove(&rnd_id);
+                continue;
             }
             _ => {
-                if *LOGGING.lock().await {
-                    println!("[Warn] Received unknown message: {:?}", msg);
+                warn!("Received unknown message: {:?}", msg);
+
+                continue;
+            }
+        };
+
+        match decoded {
+            Ok(mut command) => {
+                command.received_at = Some(received_at);
+
+                // if hash != command.hash {
+                //     if *LOGGING.lock().await {
+                //         println!("[Warn] Hashes do not match, dropping command");
+
+                //         println!("Received hash: {}", command.hash);
+                //         println!("Calculated hash: {}", hash);
+
+                //         println!("Command: {}", command.command);
+                //         println!("Length: {}", command.length);
+                //         println!("Data: {}", serde_json::to_string(&command).unwrap());
+                //     }
+
+                //     outgoing
+                //         .lock()
+                //         .await
+                //         .send(Message::Text(
+                //             "Hashes do not match, dropping command".to_string(),
+                //         ))
+                //         .await
+                //         .unwrap();
+
+                //     continue;
+                // }
+
+                // ? The handshake is handled here and not in its own task so every command after it uses the new encoding
+                let span = error_span!(
+                    "command",
+                    command = %command.command,
+                    nonce = command.nonce.as_deref().unwrap_or_default()
+                );
+
+                if command.command == "handshake" {
+                    commands::handshake::handshake(Arc::clone(&outgoing), &command, &user)
+                        .instrument(span)
+                        .await;
+
+                    continue;
                 }
+
+                // ? Every command is tracked on the client until it's done, so an admin can list and cancel it
+                // ? connect / reconnect / disconnect take and release a reference to a shared session, stopping them
+                // ? halfway could leak it. Their abort handle is dropped, so the Abortable below never aborts
+                let cancellable = !matches!(command.command.as_str(), "connect" | "reconnect" | "disconnect");
+
+                let (abort, registration) = AbortHandle::new_pair();
+                let nonce = command.nonce.clone();
+                let query_id = user.lock().await.start_query(&command, cancellable.then_some(abort));
+
+                let feature = handle_command(
+                    Arc::clone(&outgoing),
+                    command,
+                    Arc::clone(&user),
+                    Arc::clone(&users),
+                );
+
+                let running_user = Arc::clone(&user);
+                let running_outgoing = Arc::clone(&outgoing);
+
+                commands.spawn(
+                    async move {
+                        let cancelled = Abortable::new(feature, registration).await.is_err();
+
+                        // ? Sent before the command is forgotten, so a draining connection waits for the error to go out
+                        if cancelled {
+                            send_failure(&running_outgoing, &Error::Cancelled, nonce).await;
+                        }
+
+                        running_user.lock().await.running.remove(&query_id);
+                    }
+                    .instrument(span),
+                );
+            }
+            Err(e) => {
+                let nonce = frame_encoding.salvage_nonce(&data);
+
+                warn!(
+                    nonce = nonce.as_deref().unwrap_or_default(),
+                    "A User sent an invalid command: {}",
+                    String::from_utf8_lossy(&data)
+                );
+
+                send_failure(&outgoing, &e, nonce).await;
             }
         }
     }
+
+    // ? The framed protocol has no close message, so we clean up once the stream ends no matter how it ended
+    let connection_lock = Arc::clone(&user.lock().await.connection_lock);
+    let _connection_guard = connection_lock.lock().await;
+    let session_key = user.lock().await.session_key.take();
+    let mut store = users.lock().await;
+
+    store.clients.remove(&rnd_id);
+
+    metrics::CONNECTIONS.set(store.clients.len() as i64);
+
+    if let Some(key) = session_key {
+        store.release_session(&key);
+    }
+}
+
+// ? Waits for the commands that are still running, the ones that aren't done by the deadline are cancelled
+async fn drain(user: &Arc<Mutex<state::ClientState>>, commands: &TaskTracker) {
+    commands.close();
+
+    if tokio::time::timeout_at(shutdown::deadline(), commands.wait()).await.is_ok() {
+        return;
+    }
+
+    {
+        let user = user.lock().await;
+
+        warn!(commands = user.running.len(), "Cancelling the commands still running at the deadline");
+
+        for abort in user.running.values().filter_map(|query| query.abort.as_ref()) {
+            abort.abort();
+        }
+    }
+
+    // ? The cancelled commands still send their error, the ones that can't be cancelled are waited for until the
+    // ? server gives up on the connection
+    commands.wait().await;
 }
 
 async fn handle_command(
-    write: Arc<Mutex<SplitSink<WebSocketStream<TcpStream>, Message>>>,
+    write: Outgoing,
     command: Command,
     user: Arc<Mutex<state::ClientState>>,
+    users: Arc<Mutex<state::Store>>,
 ) {
+    let started = std::time::Instant::now();
+
+    // ? Unknown commands are all counted as one so clients can't blow up the number of series
+    let name = match command.command.as_str() {
+        "connect" | "disconnect" | "reconnect" | "use" | "select" | "insert" | "raw" | "admin" => {
+            command.command.clone()
+        }
+        _ => "unknown".to_string(),
+    };
+    let keyspace = match &command.keyspace {
+        Some(keyspace) => keyspace.to_string(),
+        None => user.lock().await.keyspace.clone(),
+    };
+    let table = command.table.clone().unwrap_or_default();
+    let client = Arc::clone(&user);
+
     match command.command.as_str() {
         "connect" => {
-            commands::connect::connect(Arc::clone(&write), command.data, user).await;
+            commands::connect::connect(Arc::clone(&write), &command, user, users).await;
+        }
+        "disconnect" => {
+            commands::disconnect::disconnect(Arc::clone(&write), &command, user, users).await;
+        }
+        "reconnect" => {
+            commands::reconnect::reconnect(Arc::clone(&write), &command, user, users).await;
+        }
+        "admin" => {
+            commands::admin::admin(Arc::clone(&write), &command, users).await;
+        }
+        "use" => {
+            commands::use_keyspace::use_keyspace(Arc::clone(&write), &command, user).await;
         }
         "select" => {
             commands::select::select(
@@ -206,9 +664,19 @@ async fn handle_command(
             .await;
         }
         _ => {
-            if *LOGGING.lock().await {
-                println!("[Warn] Unknown command: {:?}", command);
-            }
+            warn!("Unknown command: {:?}", command);
+
+            send_failure(&write, &Error::UnknownCommand(command.command.clone()), command.nonce.clone()).await;
         }
     }
+
+    let session = client.lock().await.session().map(|(session, _)| session);
+    let (keyspace, table) = metrics::schema_labels(session.as_deref(), &keyspace, &table);
+
+    let labels = [name.as_str(), keyspace.as_str(), table.as_str()];
+
+    metrics::COMMANDS.with_label_values(&labels).inc();
+    metrics::COMMAND_DURATION
+        .with_label_values(&labels)
+        .observe(started.elapsed().as_secs_f64());
 }
@@ -0,0 +1,230 @@
+use lazy_static::lazy_static;
+use prometheus::{
+    register_histogram_vec, register_int_counter, register_int_counter_vec, register_int_gauge,
+    register_int_gauge_vec, Encoder, HistogramVec, IntCounter, IntCounterVec, IntGauge,
+    IntGaugeVec, TextEncoder,
+};
+use scylla::transport::errors::{DbError, QueryError};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+use crate::{state::Store, util::session::ScyllaSession};
+
+lazy_static! {
+    pub static ref COMMANDS: IntCounterVec = register_int_counter_vec!(
+        "scyllatcp_commands_total",
+        "Commands handled",
+        &["command", "keyspace", "table"]
+    )
+    .unwrap();
+    pub static ref COMMAND_DURATION: HistogramVec = register_histogram_vec!(
+        "scyllatcp_command_duration_seconds",
+        "How long commands took, from being received to the response being sent",
+        &["command", "keyspace", "table"]
+    )
+    .unwrap();
+    pub static ref ERRORS: IntCounterVec = register_int_counter_vec!(
+        "scyllatcp_errors_total",
+        "Errors sent to clients",
+        &["code"]
+    )
+    .unwrap();
+    pub static ref CONNECTIONS: IntGauge =
+        register_int_gauge!("scyllatcp_connections", "Connected clients").unwrap();
+    pub static ref IN_FLIGHT: IntGauge =
+        register_int_gauge!("scyllatcp_in_flight_queries", "Queries currently running").unwrap();
+    pub static ref BYTES_RECEIVED: IntCounter =
+        register_int_counter!("scyllatcp_received_bytes_total", "Bytes received from clients").unwrap();
+    pub static ref BYTES_SENT: IntCounter =
+        register_int_counter!("scyllatcp_sent_bytes_total", "Bytes sent to clients").unwrap();
+    static ref SESSIONS: IntGauge =
+        register_int_gauge!("scyllatcp_sessions", "Open (shared) sessions").unwrap();
+    // ? The driver keeps its own counters, they are copied in on every scrape so these are gauges
+    static ref DRIVER_QUERIES: IntGaugeVec = register_int_gauge_vec!(
+        "scyllatcp_driver_queries",
+        "Queries the driver ran (including pages)",
+        &["session"]
+    )
+    .unwrap();
+    static ref DRIVER_ERRORS: IntGaugeVec = register_int_gauge_vec!(
+        "scyllatcp_driver_errors",
+        "Queries the driver failed to run (including pages)",
+        &["session"]
+    )
+    .unwrap();
+    static ref DRIVER_RETRIES: IntGaugeVec = register_int_gauge_vec!(
+        "scyllatcp_driver_retries",
+        "Retries the driver did",
+        &["session"]
+    )
+    .unwrap();
+    static ref DRIVER_LATENCY: IntGaugeVec = register_int_gauge_vec!(
+        "scyllatcp_driver_latency_avg_ms",
+        "Average query latency seen by the driver",
+        &["session"]
+    )
+    .unwrap();
+    static ref DRIVER_NODE_UP: IntGaugeVec = register_int_gauge_vec!(
+        "scyllatcp_driver_node_up",
+        "1 if the driver has a working connection pool to the node",
+        &["session", "node", "datacenter"]
+    )
+    .unwrap();
+}
+
+// ? The metrics are registered the first time they are used, this makes sure all of them show up from the start
+pub fn init() {
+    lazy_static::initialize(&COMMANDS);
+    lazy_static::initialize(&COMMAND_DURATION);
+    lazy_static::initialize(&ERRORS);
+    lazy_static::initialize(&CONNECTIONS);
+    lazy_static::initialize(&IN_FLIGHT);
+    lazy_static::initialize(&BYTES_RECEIVED);
+    lazy_static::initialize(&BYTES_SENT);
+    lazy_static::initialize(&SESSIONS);
+    lazy_static::initialize(&DRIVER_QUERIES);
+    lazy_static::initialize(&DRIVER_ERRORS);
+    lazy_static::initialize(&DRIVER_RETRIES);
+    lazy_static::initialize(&DRIVER_LATENCY);
+    lazy_static::initialize(&DRIVER_NODE_UP);
+}
+
+// ? Counts a query as in flight until it's dropped
+pub struct InFlight;
+
+impl InFlight {
+    pub fn start() -> Self {
+        IN_FLIGHT.inc();
+
+        Self
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        IN_FLIGHT.dec();
+    }
+}
+
+pub fn record_error(code: &str) {
+    ERRORS.with_label_values(&[code]).inc();
+}
+
+// ? The keyspace and table come from the client, so anything the cluster's schema doesn't know about is counted as
+// ? "unknown", else every typo (or a client sending random names) would be a new series
+pub fn schema_labels(session: Option<&ScyllaSession>, keyspace: &str, table: &str) -> (String, String) {
+    let unknown = |name: &str| match name.is_empty() {
+        true => String::new(),
+        false => "unknown".to_string(),
+    };
+
+    let cluster_data = match session {
+        Some(session) => session.session.get_cluster_data(),
+        None => return (unknown(keyspace), unknown(table)),
+    };
+
+    match cluster_data.get_keyspace_info().get(keyspace) {
+        Some(info) if table.is_empty() || info.tables.contains_key(table) || info.views.contains_key(table) => {
+            (keyspace.to_string(), table.to_string())
+        }
+        Some(_) => (keyspace.to_string(), unknown(table)),
+        None => (unknown(keyspace), unknown(table)),
+    }
+}
+
+// ? A short name for the error, used as the "code" label
+pub fn error_code(error: &QueryError) -> &'static str {
+    match error {
+        QueryError::DbError(error, _) => match error {
+            DbError::SyntaxError => "syntax_error",
+            DbError::Invalid => "invalid",
+            DbError::AlreadyExists { .. } => "already_exists",
+            DbError::FunctionFailure { .. } => "function_failure",
+            DbError::AuthenticationError => "authentication_error",
+            DbError::Unauthorized => "unauthorized",
+            DbError::ConfigError => "config_error",
+            DbError::Unavailable { .. } => "unavailable",
+            DbError::Overloaded => "overloaded",
+            DbError::IsBootstrapping => "is_bootstrapping",
+            DbError::TruncateError => "truncate_error",
+            DbError::ReadTimeout { .. } => "read_timeout",
+            DbError::WriteTimeout { .. } => "write_timeout",
+            DbError::ReadFailure { .. } => "read_failure",
+            DbError::WriteFailure { .. } => "write_failure",
+            DbError::Unprepared { .. } => "unprepared",
+            DbError::ServerError => "server_error",
+            DbError::ProtocolError => "protocol_error",
+            DbError::RateLimitReached { .. } => "rate_limit_reached",
+            DbError::Other(_) => "db_error",
+        },
+        QueryError::BadQuery(_) => "bad_query",
+        QueryError::IoError(_) => "io_error",
+        QueryError::ProtocolError(_) | QueryError::InvalidMessage(_) => "protocol_error",
+        QueryError::TimeoutError | QueryError::RequestTimeout(_) => "client_timeout",
+        QueryError::TooManyOrphanedStreamIds(_) | QueryError::UnableToAllocStreamId => "no_stream",
+        QueryError::TranslationError(_) => "translation_error",
+    }
+}
+
+// ? Sessions are labeled with the start of their key, which is enough to tell them apart
+async fn collect_driver_metrics(users: &Arc<Mutex<Store>>) {
+    let sessions = users
+        .lock()
+        .await
+        .sessions
+        .iter()
+        .map(|(key, shared)| (key.chars().take(12).collect::<String>(), Arc::clone(&shared.session)))
+        .collect::<Vec<_>>();
+
+    SESSIONS.set(sessions.len() as i64);
+
+    // ? Sessions that were closed since the last scrape shouldn't show up anymore
+    DRIVER_QUERIES.reset();
+    DRIVER_ERRORS.reset();
+    DRIVER_RETRIES.reset();
+    DRIVER_LATENCY.reset();
+    DRIVER_NODE_UP.reset();
+
+    for (key, session) in sessions {
+        let metrics = session.session.get_metrics();
+
+        DRIVER_QUERIES
+            .with_label_values(&[&key])
+            .set((metrics.get_queries_num() + metrics.get_queries_iter_num()) as i64);
+        DRIVER_ERRORS
+            .with_label_values(&[&key])
+            .set((metrics.get_errors_num() + metrics.get_errors_iter_num()) as i64);
+        DRIVER_RETRIES
+            .with_label_values(&[&key])
+            .set(metrics.get_retries_num() as i64);
+
+        if let Ok(latency) = metrics.get_latency_avg_ms() {
+            DRIVER_LATENCY.with_label_values(&[&key]).set(latency as i64);
+        }
+
+        for node in session.session.get_cluster_data().get_nodes_info() {
+            let up = node.is_enabled() && !node.is_down();
+
+            DRIVER_NODE_UP
+                .with_label_values(&[
+                    &key,
+                    &node.address.to_string(),
+                    node.datacenter.as_deref().unwrap_or_default(),
+                ])
+                .set(up as i64);
+        }
+    }
+}
+
+// ? The body for /metrics in the prometheus text format
+pub async fn render(users: &Arc<Mutex<Store>>) -> String {
+    collect_driver_metrics(users).await;
+
+    let mut buffer = Vec::new();
+
+    if let Err(e) = TextEncoder::new().encode(&prometheus::gather(), &mut buffer) {
+        tracing::warn!("Failed to encode the metrics: {}", e);
+    }
+
+    String::from_utf8(buffer).unwrap_or_default()
+}
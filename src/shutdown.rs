@@ -0,0 +1,65 @@
+use std::{sync::OnceLock, time::Duration};
+use tokio::time::Instant;
+use tokio_util::{sync::CancellationToken, task::TaskTracker};
+use tracing::warn;
+
+static TOKEN: OnceLock<CancellationToken> = OnceLock::new();
+static CONNECTIONS: OnceLock<TaskTracker> = OnceLock::new();
+static DEADLINE: OnceLock<Instant> = OnceLock::new();
+
+// ? Cancelled once we start shutting down, the listeners stop accepting and every connection starts draining
+pub fn token() -> &'static CancellationToken {
+    TOKEN.get_or_init(CancellationToken::new)
+}
+
+// ? Every connection is spawned on this, so the shutdown can wait for all of them to be closed
+pub fn connections() -> &'static TaskTracker {
+    CONNECTIONS.get_or_init(TaskTracker::new)
+}
+
+// ? Commands that are still running at this point are cancelled
+pub fn deadline() -> Instant {
+    *DEADLINE.get_or_init(Instant::now)
+}
+
+pub fn begin(timeout: Duration) -> Instant {
+    let deadline = *DEADLINE.get_or_init(|| Instant::now() + timeout);
+
+    token().cancel();
+    connections().close();
+
+    deadline
+}
+
+#[cfg(unix)]
+pub async fn signal() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut terminate = match signal(SignalKind::terminate()) {
+        Ok(terminate) => terminate,
+        Err(e) => {
+            warn!("Failed to listen for SIGTERM: {}", e);
+
+            return interrupt().await;
+        }
+    };
+
+    tokio::select! {
+        _ = interrupt() => {}
+        _ = terminate.recv() => {}
+    }
+}
+
+#[cfg(not(unix))]
+pub async fn signal() {
+    interrupt().await
+}
+
+// ? If we can't listen for ctrl-c we never shut down from it, instead of shutting down right away
+async fn interrupt() {
+    if let Err(e) = tokio::signal::ctrl_c().await {
+        warn!("Failed to listen for SIGINT: {}", e);
+
+        std::future::pending::<()>().await;
+    }
+}
@@ -1,39 +1,255 @@
-use scylla::Session;
-use std::{collections::HashMap, sync::Arc};
-use tokio::sync::Mutex;
+use crate::util::session::ScyllaSession;
+use chrono::{DateTime, Utc};
+use futures_util::future::AbortHandle;
+use std::{
+    collections::HashMap,
+    ops::Deref,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+use tokio::sync::{Mutex, Notify};
 
+use crate::config::Config;
+use crate::structs::common::{Command, CommandData};
+
+// ? Query ids are unique across connections, so an admin can cancel one without knowing the client
+static NEXT_QUERY_ID: AtomicU64 = AtomicU64::new(1);
+
+// ? Who the client is according to the certificate it used (only set on TLS listeners with a CA)
+#[derive(Clone, Debug)]
+pub struct ClientIdentity {
+    pub common_name: Option<String>,
+    pub fingerprint: String, // ? sha256 of the certificate, hex encoded
+}
+
+// ? A command that is still running, aborting it drops the command wherever it is
+#[derive(Debug)]
+pub struct RunningQuery {
+    pub command: String,
+    pub keyspace: Option<String>,
+    pub table: Option<String>,
+    pub nonce: Option<String>,
+    pub cql: Option<String>,
+    pub started_at: DateTime<Utc>,
+    pub abort: Option<AbortHandle>, // ? None for the commands that can't be cancelled halfway
+}
+
+// ? Handlers only hold the lock long enough to clone the session out, so a connection can run many queries at once
 #[derive(Debug)]
 pub struct ClientState {
+    pub id: String, // ? The random id the connection is logged with
+    pub peer: String, // ? The address of the client (or unix:path for unix sockets)
     pub connected: bool,
     pub keyspace: String,
-    pub session: Option<Arc<Mutex<Session>>>,
+    pub session: Option<Arc<ScyllaSession>>,
+    pub session_key: Option<String>, // ? The key of the shared session in the store, so we can release it
+    pub identity: Option<ClientIdentity>,
+    pub connection_lock: Arc<Mutex<()>>, // ? Held while connecting / disconnecting so those never run at the same time
+    pub connected_at: DateTime<Utc>,
+    pub total_queries: u64,
+    pub running: HashMap<u64, RunningQuery>,
+    pub kick: Arc<Notify>, // ? Notified when an admin disconnects the client
 }
 
 impl ClientState {
-    pub fn new(connected: bool, keyspace: &str, session: Option<Session>) -> Self {
-        let session = if connected {
-            Some(Arc::new(Mutex::new(session.unwrap())))
-        } else {
-            None
-        };
+    pub fn new(connected: bool, keyspace: &str, session: Option<ScyllaSession>) -> Self {
+        let session = if connected { session.map(Arc::new) } else { None };
 
         Self {
+            id: String::new(),
+            peer: String::new(),
             connected,
             keyspace: keyspace.to_string(),
             session,
+            session_key: None,
+            identity: None,
+            connection_lock: Arc::new(Mutex::new(())),
+            connected_at: Utc::now(),
+            total_queries: 0,
+            running: HashMap::new(),
+            kick: Arc::new(Notify::new()),
+        }
+    }
+
+    // ? Returns the id the command is tracked under, the caller removes it once the command is done
+    pub fn start_query(&mut self, command: &Command, abort: Option<AbortHandle>) -> u64 {
+        let id = NEXT_QUERY_ID.fetch_add(1, Ordering::Relaxed);
+
+        let cql = match &command.data {
+            CommandData::Raw(raw_data) => Some(raw_data.query.clone()),
+            _ => None,
+        };
+
+        self.total_queries += 1;
+        self.running.insert(
+            id,
+            RunningQuery {
+                command: command.command.clone(),
+                keyspace: command.keyspace.clone(),
+                table: command.table.clone(),
+                nonce: command.nonce.clone(),
+                cql,
+                started_at: Utc::now(),
+                abort,
+            },
+        );
+
+        id
+    }
+
+    // ? The session and keyspace to run a query with, or None if we aren't connected
+    pub fn session(&self) -> Option<(Arc<ScyllaSession>, String)> {
+        if !self.connected {
+            return None;
         }
+
+        Some((Arc::clone(self.session.as_ref()?), self.keyspace.clone()))
+    }
+}
+
+// ? A session shared by every client that connected with the same contact points, credentials and options
+#[derive(Debug)]
+pub struct SharedSession<S = ScyllaSession> {
+    pub session: Arc<S>,
+    pub users: usize,
+}
+
+// ? The shared sessions by key, counting the clients that use each of them
+#[derive(Debug)]
+pub struct SharedSessions<S = ScyllaSession>(HashMap<String, SharedSession<S>>);
+
+impl<S> Default for SharedSessions<S> {
+    fn default() -> Self {
+        Self(HashMap::new())
+    }
+}
+
+impl<S> Deref for SharedSessions<S> {
+    type Target = HashMap<String, SharedSession<S>>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<S> SharedSessions<S> {
+    pub fn acquire(&mut self, key: &str) -> Option<Arc<S>> {
+        let shared = self.0.get_mut(key)?;
+
+        shared.users += 1;
+
+        Some(Arc::clone(&shared.session))
+    }
+
+    // ? If someone else built the same session while we were building ours, theirs wins and ours is dropped
+    pub fn insert(&mut self, key: &str, session: S) -> Arc<S> {
+        if let Some(session) = self.acquire(key) {
+            return session;
+        }
+
+        let session = Arc::new(session);
+
+        self.0.insert(
+            key.to_string(),
+            SharedSession {
+                session: Arc::clone(&session),
+                users: 1,
+            },
+        );
+
+        session
+    }
+
+    // ? Once the last user is gone the session is dropped, which closes its connections
+    pub fn release(&mut self, key: &str) {
+        if let Some(shared) = self.0.get_mut(key) {
+            shared.users = shared.users.saturating_sub(1);
+
+            if shared.users == 0 {
+                self.0.remove(key);
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.0.clear();
     }
 }
 
 #[derive(Debug)]
 pub struct Store {
     pub clients: HashMap<String, Arc<Mutex<ClientState>>>,
+    pub sessions: SharedSessions,
+    pub config: Arc<Config>,
 }
 
 impl Store {
-    pub fn new() -> Self {
+    pub fn new(config: Arc<Config>) -> Self {
         Self {
             clients: HashMap::new(),
+            sessions: SharedSessions::default(),
+            config,
         }
     }
+
+    pub fn acquire_session(&mut self, key: &str) -> Option<Arc<ScyllaSession>> {
+        self.sessions.acquire(key)
+    }
+
+    pub fn insert_session(&mut self, key: &str, session: ScyllaSession) -> Arc<ScyllaSession> {
+        self.sessions.insert(key, session)
+    }
+
+    pub fn release_session(&mut self, key: &str) {
+        self.sessions.release(key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SharedSessions;
+    use std::sync::Arc;
+
+    #[test]
+    fn sessions_are_shared_until_the_last_user_releases_them() {
+        let mut sessions = SharedSessions::default();
+
+        assert!(sessions.acquire("a").is_none());
+
+        let first = sessions.insert("a", 1);
+        let second = sessions.acquire("a").unwrap();
+
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!(sessions["a"].users, 2);
+
+        sessions.release("a");
+        assert_eq!(sessions["a"].users, 1);
+
+        sessions.release("a");
+        assert!(!sessions.contains_key("a"));
+
+        // ? Releasing a session that's already gone doesn't do anything
+        sessions.release("a");
+        assert!(sessions.is_empty());
+    }
+
+    #[test]
+    fn a_session_built_twice_keeps_the_first_one() {
+        let mut sessions = SharedSessions::default();
+
+        let first = sessions.insert("a", 1);
+        let second = sessions.insert("a", 2);
+
+        assert_eq!(*second, 1);
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!(sessions["a"].users, 2);
+
+        sessions.insert("b", 3);
+        sessions.release("a");
+
+        assert_eq!(sessions["a"].users, 1);
+        assert_eq!(sessions["b"].users, 1);
+    }
 }
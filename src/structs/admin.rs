@@ -0,0 +1,55 @@
+use serde::{Deserialize, Serialize};
+
+// ? action is "clients", "queries", "kick" (needs client) or "cancel" (needs query)
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AdminData {
+    pub token: String,
+    pub action: String,
+    pub client: Option<String>,
+    pub query: Option<u64>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AdminResponse {
+    pub success: bool,
+    pub error: Option<String>,
+    #[serde(default)]
+    pub warnings: Vec<String>, // ? Always empty, it's there so every response has the same shape
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clients: Option<Vec<ClientInfo>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub queries: Option<Vec<RunningQueryInfo>>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ClientInfo {
+    pub id: String,
+    pub peer: String,
+    #[serde(rename = "commonName")]
+    pub common_name: Option<String>,
+    #[serde(rename = "connectedAt")]
+    pub connected_at: String,
+    pub connected: bool, // ? false until the client has connected to Scylla
+    pub keyspace: String,
+    #[serde(rename = "inFlight")]
+    pub in_flight: usize,
+    #[serde(rename = "totalQueries")]
+    pub total_queries: u64,
+    pub queries: Vec<RunningQueryInfo>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct RunningQueryInfo {
+    pub id: u64, // ? What cancel takes
+    pub client: String,
+    pub command: String,
+    pub keyspace: Option<String>,
+    pub table: Option<String>,
+    pub nonce: Option<String>,
+    pub cql: Option<String>, // ? Only known up front for raw
+    pub cancellable: bool, // ? false for connect, reconnect and disconnect
+    #[serde(rename = "startedAt")]
+    pub started_at: String,
+    #[serde(rename = "elapsedMs")]
+    pub elapsed_ms: f64,
+}
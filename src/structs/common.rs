@@ -1,22 +1,37 @@
-use std::{borrow::BorrowMut, collections::HashMap, fmt::Debug, hash::Hash, io::Stderr};
+use std::{fmt::Debug, time::Instant};
 
 use indexmap::IndexMap;
-use scylla::{frame::response::result::ColumnType, serialize::{row::SerializeRow, value::SerializeCql}, FromUserType, SerializeCql};
-use serde::{Deserialize, Serialize};
+use scylla::{
+    frame::{response::result::ColumnType, value::Counter},
+    serialize::value::{BuiltinSerializationError, BuiltinSerializationErrorKind, SerializeCql},
+};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use tracing::error;
 
 use super::{
-    connect::{ConnectData, ConnectResponse},
+    admin::{AdminData, AdminResponse},
+    connect::{ConnectData, ConnectProfileData, ConnectResponse, EmptyData, UseData},
+    handshake::{HandshakeData, HandshakeResponse},
     insert::{InsertData, InsertResponse},
     raw::RawData,
     select::SelectData,
+    shutdown::ShutdownNotice,
+    tracing::TracingData,
 };
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(untagged)]
 pub enum Value {
+    // ? JSON gets a 0x prefixed hex string (like a CQL blob literal) while MessagePack and CBOR get the raw bytes
+    // ? It has to come before Str, a String happily deserializes from bytes that happen to be valid utf8
+    #[serde(serialize_with = "serialize_blob", deserialize_with = "deserialize_blob")]
+    Blob(Vec<u8>),
     Str(String),
     Bool(bool),
     Num(i32),
+    // ? JSON can't hold a 64 bit int safely so it gets sent as a string there, MessagePack and CBOR get the real number
+    #[serde(serialize_with = "serialize_bigint")]
+    BigInt(i64),
     Null,
     Array(Vec<Value>),
     Map(Vec<(Value, Value)>),
@@ -24,16 +39,41 @@ pub enum Value {
     // Object(HashMap<String, Value>),
 }
 
+impl Value {
+    // ? The name we log for a bound value when the value itself is redacted
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Str(_) => "string",
+            Value::Bool(_) => "bool",
+            Value::Num(_) => "int",
+            Value::BigInt(_) => "bigint",
+            Value::Blob(_) => "blob",
+            Value::Null => "null",
+            Value::Array(_) => "list",
+            Value::Map(_) => "map",
+            Value::Date(_) => "date",
+        }
+    }
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(untagged)]
 pub enum CommandData {
     Select(SelectData),
     Insert(InsertData),
     Connect(ConnectData),
+    ConnectProfile(ConnectProfileData),
     SelectResponse(QueryResult),
     InsertResponse(InsertResponse),
     Raw(RawData),
     ConnectResponse(ConnectResponse),
+    Handshake(HandshakeData),
+    HandshakeResponse(HandshakeResponse),
+    Admin(AdminData),
+    AdminResponse(AdminResponse),
+    Shutdown(ShutdownNotice),
+    Use(UseData),
+    Empty(EmptyData),
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -45,58 +85,225 @@ pub struct Command {
     pub data: CommandData,
     pub length: usize, // ? The client sends the length of the data
     pub nonce: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>, // ? The execution profile to run the command with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idempotent: Option<bool>, // ? Only idempotent statements are retried after they may have been applied
+    #[serde(rename = "retryPolicy", default, skip_serializing_if = "Option::is_none")]
+    pub retry_policy: Option<String>, // ? "none", "default" or "downgrading"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trace: Option<bool>, // ? Trace the statement and send back what Scylla recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stats: Option<bool>, // ? Send back where the time went (see Stats)
+    #[serde(skip)]
+    pub received_at: Option<Instant>, // ? When the frame arrived, the start of the queue time
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct QueryResult {
     pub result: Vec<IndexMap<String, Value>>,
     pub error: Option<String>,
+    #[serde(default)]
+    pub warnings: Vec<String>, // ? What Scylla warned about, e.g large batches or too many tombstones
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub execution: Option<ExecutionInfo>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tracing: Option<TracingData>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stats: Option<Stats>,
+}
+
+// ? What the driver did to run the statement
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ExecutionInfo {
+    pub attempts: usize, // ? 1 unless the statement was retried or speculated
+    #[serde(rename = "speculativeWon")]
+    pub speculative_won: bool, // ? true if the result came from a speculative attempt
+}
+
+// ? Server side timings in milliseconds, so a client can tell the time spent in the server from the time spent in Scylla
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Stats {
+    #[serde(rename = "queueMs")]
+    pub queue_ms: f64, // ? From the frame arriving to the statement being sent
+    #[serde(rename = "executionMs")]
+    pub execution_ms: f64, // ? The driver, including retries and fetching the trace
+    #[serde(rename = "serializationMs")]
+    pub serialization_ms: f64, // ? Turning the rows into the response and encoding it
+    pub coordinator: Option<String>, // ? The node that answered (or the last one tried)
+    pub attempts: usize,
+    pub rows: usize,
+    pub bytes: usize, // ? The encoded response without this block
+}
+
+impl CommandData {
+    // ? Only the responses of commands that run a statement have stats
+    pub fn set_stats(&mut self, stats: Stats) {
+        match self {
+            CommandData::SelectResponse(response) => response.stats = Some(stats),
+            CommandData::InsertResponse(response) => response.stats = Some(stats),
+            _ => {}
+        }
+    }
+}
+
+fn serialize_bigint<S: Serializer>(value: &i64, serializer: S) -> Result<S::Ok, S::Error> {
+    if serializer.is_human_readable() {
+        serializer.serialize_str(&value.to_string())
+    } else {
+        serializer.serialize_i64(*value)
+    }
+}
+
+fn serialize_blob<S: Serializer>(value: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    if serializer.is_human_readable() {
+        serializer.serialize_str(&blob_to_hex(value))
+    } else {
+        serializer.serialize_bytes(value)
+    }
+}
+
+pub fn blob_to_hex(value: &[u8]) -> String {
+    let mut hex = String::with_capacity(2 + value.len() * 2);
+
+    hex.push_str("0x");
+
+    for byte in value {
+        hex.push_str(&format!("{:02x}", byte));
+    }
+
+    hex
+}
+
+// ? None if it isn't a 0x prefixed hex string
+pub fn blob_from_hex(value: &str) -> Option<Vec<u8>> {
+    let hex = value.strip_prefix("0x").or_else(|| value.strip_prefix("0X"))?;
+
+    if hex.len() % 2 != 0 || !hex.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(&hex[index..index + 2], 16).ok())
+        .collect()
+}
+
+// ? Only accepts real bytes (MessagePack bin / CBOR byte strings), so strings and arrays still end up as Str / Array
+fn deserialize_blob<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+    struct BlobVisitor;
+
+    impl<'de> serde::de::Visitor<'de> for BlobVisitor {
+        type Value = Vec<u8>;
+
+        fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+            formatter.write_str("a byte array")
+        }
+
+        fn visit_bytes<E: serde::de::Error>(self, value: &[u8]) -> Result<Self::Value, E> {
+            Ok(value.to_vec())
+        }
+
+        fn visit_byte_buf<E: serde::de::Error>(self, value: Vec<u8>) -> Result<Self::Value, E> {
+            Ok(value)
+        }
+    }
+
+    deserializer.deserialize_bytes(BlobVisitor)
+}
+
+// ? Whether a number is a Num or a BigInt only depends on its size, so it's converted to whatever int the column holds
+fn serialize_int<'b>(
+    value: i64,
+    typ: &ColumnType,
+    writer: scylla::serialize::CellWriter<'b>,
+) -> Result<scylla::serialize::writers::WrittenCellProof<'b>, scylla::serialize::SerializationError> {
+    let out_of_range = || {
+        scylla::serialize::SerializationError::new(BuiltinSerializationError {
+            rust_name: std::any::type_name::<i64>(),
+            got: typ.clone(),
+            kind: BuiltinSerializationErrorKind::ValueOverflow,
+        })
+    };
+
+    match typ {
+        ColumnType::BigInt => SerializeCql::serialize(&value, typ, writer),
+        ColumnType::Counter => SerializeCql::serialize(&Counter(value), typ, writer),
+        ColumnType::Int => SerializeCql::serialize(&i32::try_from(value).map_err(|_| out_of_range())?, typ, writer),
+        ColumnType::SmallInt => SerializeCql::serialize(&i16::try_from(value).map_err(|_| out_of_range())?, typ, writer),
+        ColumnType::TinyInt => SerializeCql::serialize(&i8::try_from(value).map_err(|_| out_of_range())?, typ, writer),
+        // ? Not an int column, the driver's type check gives the error
+        _ => SerializeCql::serialize(&value, typ, writer),
+    }
 }
 
 impl SerializeCql for Value {
     fn serialize<'b>(
         &self,
-        typ: &scylla::frame::response::result::ColumnType,
-        mut writer: scylla::serialize::CellWriter<'b>,
+        typ: &ColumnType,
+        writer: scylla::serialize::CellWriter<'b>,
     ) -> Result<
         scylla::serialize::writers::WrittenCellProof<'b>,
         scylla::serialize::SerializationError,
     > {
         match self {
-            &Value::Str(ref value) => {
-                // scylla::serialize::value::SerializeCql::serialize(value, typ, writer)
-                match scylla::serialize::value::SerializeCql::serialize(value, typ, writer) {
+            Value::Str(value) => {
+                // ? Blobs come in from JSON as hex strings, the same way we send them out
+                let result = match (typ, blob_from_hex(value)) {
+                    (ColumnType::Blob, Some(blob)) => SerializeCql::serialize(&blob, typ, writer),
+                    _ => scylla::serialize::value::SerializeCql::serialize(value, typ, writer),
+                };
+
+                match result {
                     Ok(value) => Ok(value),
                     Err(err) => {
-                        println!("[Error] Failed to serialize Str: {:?}", err);
+                        error!("Failed to serialize Str: {:?}", err);
                         Err(err)
                     }
                 }
             }
-            &Value::Num(ref value) => {
+            Value::Num(value) => {
                 // scylla::serialize::value::SerializeCql::serialize(value, typ, writer)
-                match scylla::serialize::value::SerializeCql::serialize(value, typ, writer) {
+                match serialize_int(i64::from(*value), typ, writer) {
                     Ok(value) => Ok(value),
                     Err(err) => {
-                        println!("[Error] Failed to serialize Num: {:?}", err);
+                        error!("Failed to serialize Num: {:?}", err);
                         Err(err)
                     }
                 }
             }
-            // &Value::Int(ref value) => {
+            // Value::Int(value) => {
             //     scylla::serialize::value::SerializeCql::serialize(value, typ, writer)
             // }
-            &Value::Bool(ref value) => {
+            Value::BigInt(value) => {
+                match serialize_int(*value, typ, writer) {
+                    Ok(value) => Ok(value),
+                    Err(err) => {
+                        error!("Failed to serialize BigInt: {:?}", err);
+                        Err(err)
+                    }
+                }
+            }
+            Value::Blob(value) => {
+                match scylla::serialize::value::SerializeCql::serialize(value, typ, writer) {
+                    Ok(value) => Ok(value),
+                    Err(err) => {
+                        error!("Failed to serialize Blob: {:?}", err);
+                        Err(err)
+                    }
+                }
+            }
+            Value::Bool(value) => {
                 // scylla::serialize::value::SerializeCql::serialize(value, typ, writer)
                 match scylla::serialize::value::SerializeCql::serialize(value, typ, writer) {
                     Ok(value) => Ok(value),
                     Err(err) => {
-                        println!("[Error] Failed to serialize Bool: {:?}", err);
+                        error!("Failed to serialize Bool: {:?}", err);
                         Err(err)
                     }
                 }
             }
-            &Value::Null => {
+            Value::Null => {
                 // scylla::serialize::value::SerializeCql::serialize(&None::<String>, typ, writer)
                 match scylla::serialize::value::SerializeCql::serialize(
                     &None::<String>,
@@ -105,46 +312,46 @@ impl SerializeCql for Value {
                 ) {
                     Ok(value) => Ok(value),
                     Err(err) => {
-                        println!("[Error] Failed to serialize Null: {:?}", err);
+                        error!("Failed to serialize Null: {:?}", err);
                         Err(err)
                     }
                 }
             }
-            &Value::Array(ref value) => {
+            Value::Array(value) => {
                 // scylla::serialize::value::SerializeCql::serialize(value, typ, writer)
                 match scylla::serialize::value::SerializeCql::serialize(value, typ, writer) {
                     Ok(value) => Ok(value),
                     Err(err) => {
-                        println!("[Error] Failed to serialize Array: {:?}", err);
+                        error!("Failed to serialize Array: {:?}", err);
                         Err(err)
                     }
                 }
             }
-            &Value::Map(ref value) => {
+            Value::Map(value) => {
                 // scylla::serialize::value::SerializeCql::serialize(value, typ, writer)
                 match scylla::serialize::value::SerializeCql::serialize(value, typ, writer) {
                     Ok(value) => Ok(value),
                     Err(err) => {
-                        println!("[Error] Failed to serialize Map: {:?}", err);
+                        error!("Failed to serialize Map: {:?}", err);
                         Err(err)
                     }
                 }
             }
-            &Value::Date(ref value) => {
+            Value::Date(value) => {
                 // scylla::serialize::value::SerializeCql::serialize(value, typ, writer)
                 match scylla::serialize::value::SerializeCql::serialize(value, typ, writer) {
                     Ok(value) => Ok(value),
                     Err(err) => {
-                        println!("[Error] Failed to serialize Date: {:?}", err);
+                        error!("Failed to serialize Date: {:?}", err);
                         Err(err)
                     }
                 }
             }
-        //     &Value::Object(ref value) => {
+        //     Value::Object(value) => {
         //         match writer.into_value_builder().append_bytes(value.to_string().as_bytes()).serialize(ctx, writer) {
         //             Ok(value) => Ok(value),
         //             Err(err) => {
-        //                 println!("[Error] Failed to serialize Object: {:?}", err);
+        //                 error!("Failed to serialize Object: {:?}", err);
         //                 Err(scylla::serialize::SerializationError::new(std::io::Error::new(
         //                     std::io::ErrorKind::Other,
         //                     "Failed to serialize Object",
@@ -156,3 +363,60 @@ impl SerializeCql for Value {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use scylla::{
+        frame::response::result::ColumnType,
+        serialize::{value::SerializeCql, CellWriter},
+    };
+
+    use super::{blob_from_hex, blob_to_hex, Value};
+
+    fn serialize(value: &Value, typ: &ColumnType) -> Option<Vec<u8>> {
+        let mut buf = Vec::new();
+
+        SerializeCql::serialize(value, typ, CellWriter::new(&mut buf)).ok()?;
+
+        // ? Skip the 4 byte length
+        Some(buf[4..].to_vec())
+    }
+
+    #[test]
+    fn ints_follow_the_column_type() {
+        assert_eq!(serialize(&Value::Num(7), &ColumnType::BigInt), Some(7i64.to_be_bytes().to_vec()));
+        assert_eq!(serialize(&Value::Num(7), &ColumnType::Counter), Some(7i64.to_be_bytes().to_vec()));
+        assert_eq!(serialize(&Value::Num(7), &ColumnType::Int), Some(7i32.to_be_bytes().to_vec()));
+        assert_eq!(serialize(&Value::Num(-7), &ColumnType::SmallInt), Some((-7i16).to_be_bytes().to_vec()));
+        assert_eq!(serialize(&Value::Num(7), &ColumnType::TinyInt), Some(7i8.to_be_bytes().to_vec()));
+        assert_eq!(serialize(&Value::BigInt(7), &ColumnType::Int), Some(7i32.to_be_bytes().to_vec()));
+        assert_eq!(serialize(&Value::BigInt(i64::MAX), &ColumnType::BigInt), Some(i64::MAX.to_be_bytes().to_vec()));
+    }
+
+    #[test]
+    fn blobs_round_trip_through_hex() {
+        let blob = vec![0, 159, 146, 150, 255];
+
+        assert_eq!(blob_to_hex(&blob), "0x009f9296ff");
+        assert_eq!(blob_from_hex("0x009f9296ff"), Some(blob.clone()));
+        assert_eq!(blob_from_hex("0X009F9296FF"), Some(blob));
+        assert_eq!(blob_from_hex("0x"), Some(Vec::new()));
+        assert_eq!(blob_from_hex("0x0"), None);
+        assert_eq!(blob_from_hex("0x+f"), None);
+        assert_eq!(blob_from_hex("hello"), None);
+    }
+
+    #[test]
+    fn hex_strings_are_blobs_only_for_blob_columns() {
+        assert_eq!(serialize(&Value::Str("0x00ff".to_string()), &ColumnType::Blob), Some(vec![0, 255]));
+        assert_eq!(serialize(&Value::Str("0x00ff".to_string()), &ColumnType::Text), Some(b"0x00ff".to_vec()));
+    }
+
+    #[test]
+    fn ints_out_of_range_are_rejected() {
+        assert_eq!(serialize(&Value::Num(128), &ColumnType::TinyInt), None);
+        assert_eq!(serialize(&Value::Num(-32769), &ColumnType::SmallInt), None);
+        assert_eq!(serialize(&Value::BigInt(1 << 31), &ColumnType::Int), None);
+        assert_eq!(serialize(&Value::Num(7), &ColumnType::Text), None);
+    }
+}
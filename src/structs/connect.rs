@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct CredentialsData {
@@ -14,10 +15,105 @@ pub struct ConnectData {
     pub local_data_center: String,
     pub credentials: CredentialsData,
     pub keyspace: String,
+    #[serde(rename = "permitDcFailover")]
+    pub permit_dc_failover: Option<bool>, // ? defaults to false, so we never route to another DC
+    #[serde(rename = "allowedRacks")]
+    pub allowed_racks: Option<Vec<String>>, // ? racks in the local DC we are allowed to connect to
+    #[serde(rename = "allowedHosts")]
+    pub allowed_hosts: Option<Vec<String>>, // ? hosts (ip or ip:port) we are allowed to connect to
+    pub options: Option<Box<SessionOptions>>,
+    #[serde(rename = "executionProfiles")]
+    pub execution_profiles: Option<BTreeMap<String, ExecutionProfileOptions>>, // ? a BTreeMap so the session key doesn't depend on the order
+}
+
+// ? Switches the keyspace used by commands that don't send one
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct UseData {
+    pub keyspace: String,
+}
+
+// ? For commands that don't need any data (e.g disconnect), matches any object so it has to be the last variant
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct EmptyData {}
+
+// ? Connect with a profile from the server's config instead of sending the credentials
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ConnectProfileData {
+    pub profile: String,
+    pub token: Option<String>,
+    pub keyspace: Option<String>, // ? overrides the keyspace from the profile
+}
+
+// ? All of the times are in milliseconds
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct SessionOptions {
+    #[serde(rename = "connectionTimeout")]
+    pub connection_timeout: Option<u64>,
+    #[serde(rename = "requestTimeout")]
+    pub request_timeout: Option<u64>,
+    pub compression: Option<String>, // ? "lz4" or "snappy"
+    pub tls: Option<ClusterTlsOptions>,
+    #[serde(rename = "connectionsPerShard")]
+    pub connections_per_shard: Option<usize>,
+    #[serde(rename = "keepaliveInterval")]
+    pub keepalive_interval: Option<u64>, // ? CQL level keepalive (OPTIONS requests)
+    #[serde(rename = "keepaliveTimeout")]
+    pub keepalive_timeout: Option<u64>,
+    #[serde(rename = "tcpKeepaliveInterval")]
+    pub tcp_keepalive_interval: Option<u64>,
+    #[serde(rename = "schemaAgreementTimeout")]
+    pub schema_agreement_timeout: Option<u64>,
+    pub consistency: Option<String>, // ? e.g "localQuorum", "one", "all"
+    #[serde(rename = "retryPolicy")]
+    pub retry_policy: Option<String>, // ? "none", "default" or "downgrading"
+    #[serde(rename = "speculativeExecution")]
+    pub speculative_execution: Option<SpeculativeExecutionOptions>,
+}
+
+// ? A named set of overrides for the session defaults, commands pick one with "profile"
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ExecutionProfileOptions {
+    #[serde(rename = "requestTimeout")]
+    pub request_timeout: Option<u64>,
+    pub consistency: Option<String>,
+    #[serde(rename = "serialConsistency")]
+    pub serial_consistency: Option<String>, // ? "serial" or "localSerial", used for LWT's
+    #[serde(rename = "localDataCenter")]
+    pub local_data_center: Option<String>, // ? the DC to prefer, defaults to the one from the connect data
+    #[serde(rename = "permitDcFailover")]
+    pub permit_dc_failover: Option<bool>,
+    #[serde(rename = "tokenAware")]
+    pub token_aware: Option<bool>, // ? defaults to true
+    #[serde(rename = "retryPolicy")]
+    pub retry_policy: Option<String>,
+    #[serde(rename = "speculativeExecution")]
+    pub speculative_execution: Option<SpeculativeExecutionOptions>,
+}
+
+// ? Sends the statement to another node too if the first one is slow, the driver only does this for idempotent statements
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SpeculativeExecutionOptions {
+    pub policy: String, // ? "simple" or "percentile"
+    #[serde(rename = "maxRetryCount")]
+    pub max_retry_count: usize,
+    #[serde(rename = "retryInterval")]
+    pub retry_interval: Option<u64>, // ? simple: how long (ms) to wait before every extra attempt
+    pub percentile: Option<f64>, // ? percentile: start an extra attempt once the latency passes this percentile
+}
+
+// ? The paths are on the machine the server is running on
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ClusterTlsOptions {
+    pub ca: Option<String>,
+    pub cert: Option<String>,
+    pub key: Option<String>,
+    pub verify: Option<bool>, // ? defaults to true, only turn this off for testing
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct ConnectResponse {
     pub result: String,
     pub error: Option<String>,
+    #[serde(default)]
+    pub warnings: Vec<String>, // ? Always empty, it's there so every response has the same shape
 }
\ No newline at end of file
@@ -1,6 +1,4 @@
-use chrono::Utc;
 use scylla::{frame::value::CqlTimestamp, SerializeCql, SerializeRow};
-use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Debug, SerializeRow, SerializeCql)]
 pub struct TokensTypeUDT { // name of the struct does NOT have to much
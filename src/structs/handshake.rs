@@ -0,0 +1,14 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct HandshakeData {
+    pub encoding: String, // ? "json", "msgpack" or "cbor"
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct HandshakeResponse {
+    pub encoding: String,
+    pub error: Option<String>,
+    #[serde(default)]
+    pub warnings: Vec<String>, // ? Always empty, it's there so every response has the same shape
+}
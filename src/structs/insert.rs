@@ -1,7 +1,10 @@
 use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
 
-use super::common::Value;
+use super::{
+    common::{ExecutionInfo, Stats, Value},
+    tracing::TracingData,
+};
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct InsertData {
@@ -14,4 +17,12 @@ pub struct InsertData {
 pub struct InsertResponse {
     pub success: bool,
     pub error: Option<String>,
+    #[serde(default)]
+    pub warnings: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub execution: Option<ExecutionInfo>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tracing: Option<TracingData>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stats: Option<Stats>,
 }
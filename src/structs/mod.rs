@@ -1,6 +1,12 @@
+pub mod admin;
 pub mod common;
 pub mod connect;
+pub mod handshake;
 pub mod insert;
 pub mod select;
+pub mod shutdown;
 pub mod raw;
-pub mod custom;
\ No newline at end of file
+pub mod tracing;
+// ? These are kept around for when we figure out how to insert UDT's
+#[allow(dead_code)]
+pub mod custom;
@@ -0,0 +1,9 @@
+use serde::{Deserialize, Serialize};
+
+// ? Sent to every client when the server starts shutting down, no new commands are read after it
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ShutdownNotice {
+    #[serde(rename = "retryAfter")]
+    pub retry_after: u64, // ? in milliseconds, when to try connecting again
+    pub deadline: u64, // ? in milliseconds, running commands that aren't done by then are cancelled
+}
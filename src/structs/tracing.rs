@@ -0,0 +1,19 @@
+use serde::{Deserialize, Serialize};
+
+// ? What Scylla recorded in system_traces for a traced statement, durations are in microseconds
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct TracingData {
+    pub id: String,
+    pub coordinator: Option<String>,
+    pub duration: Option<i32>,
+    pub events: Vec<TracingEventData>, // ? In the order Scylla recorded them
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct TracingEventData {
+    pub activity: Option<String>,
+    pub source: Option<String>,
+    #[serde(rename = "sourceElapsed")]
+    pub source_elapsed: Option<i32>,
+    pub thread: Option<String>,
+}
@@ -0,0 +1,131 @@
+use openssl::{
+    error::ErrorStack,
+    hash::MessageDigest,
+    nid::Nid,
+    ssl::{Ssl, SslAcceptor, SslContext, SslFiletype, SslMethod, SslVerifyMode},
+};
+use std::pin::Pin;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_openssl::SslStream;
+
+use crate::{
+    config::TlsConfig,
+    error::{self, Error},
+    state::ClientIdentity,
+    structs::connect::ClusterTlsOptions,
+};
+
+pub fn build_acceptor(tls_config: &TlsConfig) -> error::Result<SslAcceptor> {
+    let startup = |message: &'static str| move |e: ErrorStack| Error::Startup(format!("{}: {}", message, e));
+
+    // ? Without a CA there is nothing to verify the client certificate against, so requiring one would do nothing
+    if tls_config.require_client_cert && tls_config.ca.is_none() {
+        return Err(Error::Startup("requireClientCert needs a ca to verify the client certificates with".to_string()));
+    }
+
+    let mut builder = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls_server())
+        .map_err(startup("Failed to create the TLS acceptor"))?;
+
+    builder
+        .set_certificate_chain_file(&tls_config.cert)
+        .map_err(startup("Failed to load the TLS certificate"))?;
+    builder
+        .set_private_key_file(&tls_config.key, SslFiletype::PEM)
+        .map_err(startup("Failed to load the TLS private key"))?;
+    builder
+        .check_private_key()
+        .map_err(startup("The TLS private key does not match the certificate"))?;
+
+    // ? With a CA set we ask for a client certificate (mTLS), if its required we also drop clients without one
+    if let Some(ca) = &tls_config.ca {
+        builder
+            .set_ca_file(ca)
+            .map_err(startup("Failed to load the TLS client CA"))?;
+
+        let mut mode = SslVerifyMode::PEER;
+
+        if tls_config.require_client_cert {
+            mode |= SslVerifyMode::FAIL_IF_NO_PEER_CERT;
+        }
+
+        builder.set_verify(mode);
+    }
+
+    Ok(builder.build())
+}
+
+pub async fn accept<S>(
+    acceptor: &SslAcceptor,
+    stream: S,
+) -> error::Result<(SslStream<S>, Option<ClientIdentity>)>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let ssl = Ssl::new(acceptor.context())?;
+    let mut stream = SslStream::new(ssl, stream)?;
+
+    Pin::new(&mut stream).accept().await?;
+
+    // ? If we got here with a certificate, openssl already verified it against the CA
+    let identity = match stream.ssl().peer_certificate() {
+        Some(certificate) => {
+            let common_name = certificate
+                .subject_name()
+                .entries_by_nid(Nid::COMMONNAME)
+                .next()
+                .and_then(|entry| entry.data().as_utf8().ok())
+                .map(|name| name.to_string());
+
+            let fingerprint = certificate
+                .digest(MessageDigest::sha256())
+                .map(|digest| {
+                    digest
+                        .iter()
+                        .map(|byte| format!("{:02x}", byte))
+                        .collect::<String>()
+                })
+                .unwrap_or_default();
+
+            Some(ClientIdentity {
+                common_name,
+                fingerprint,
+            })
+        }
+        None => None,
+    };
+
+    Ok((stream, identity))
+}
+
+// ? The context the driver uses when it connects to the cluster
+pub fn build_cluster_context(options: &ClusterTlsOptions) -> error::Result<SslContext> {
+    let tls = |message: &'static str| move |e: ErrorStack| Error::Tls(format!("{}: {}", message, e));
+
+    let mut builder = SslContext::builder(SslMethod::tls_client())?;
+
+    if let Some(ca) = &options.ca {
+        builder
+            .set_ca_file(ca)
+            .map_err(tls("Failed to load the cluster CA"))?;
+    }
+
+    if let Some(cert) = &options.cert {
+        builder
+            .set_certificate_chain_file(cert)
+            .map_err(tls("Failed to load the client certificate"))?;
+    }
+
+    if let Some(key) = &options.key {
+        builder
+            .set_private_key_file(key, SslFiletype::PEM)
+            .map_err(tls("Failed to load the client private key"))?;
+    }
+
+    if options.verify.unwrap_or(true) {
+        builder.set_verify(SslVerifyMode::PEER);
+    } else {
+        builder.set_verify(SslVerifyMode::NONE);
+    }
+
+    Ok(builder.build())
+}
@@ -0,0 +1,117 @@
+use bytes::Bytes;
+use futures_util::{Sink, SinkExt, Stream, StreamExt, TryStreamExt};
+use std::pin::Pin;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_tungstenite::tungstenite::{
+    handshake::server::{Request, Response},
+    http::header::{HeaderValue, SEC_WEBSOCKET_PROTOCOL},
+    Error, Message,
+};
+use tokio_util::codec::{Framed, LengthDelimitedCodec};
+
+use crate::util::encoding::Encoding;
+
+// ? Both transports are turned into a stream / sink of tungstenite messages so the rest of the server doesn't care which one is used
+pub type MessageSink = Pin<Box<dyn Sink<Message, Error = Error> + Send>>;
+pub type MessageStream = Pin<Box<dyn Stream<Item = Result<Message, Error>> + Send>>;
+
+pub struct Transport {
+    pub sink: MessageSink,
+    pub stream: MessageStream,
+    pub encoding: Encoding,
+}
+
+// ? The handshake callback has to return tungstenite's ErrorResponse, which we can't make smaller
+#[allow(clippy::result_large_err)]
+pub async fn accept_websocket<S>(stream: S) -> Result<Transport, Error>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let mut encoding = Encoding::Json;
+
+    // ? The client can pick the encoding with a subprotocol (e.g "scyllatcp.msgpack"), else it has to send a handshake command
+    let ws_stream = tokio_tungstenite::accept_hdr_async(stream, |request: &Request, mut response: Response| {
+        let requested = request
+            .headers()
+            .get(SEC_WEBSOCKET_PROTOCOL)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.split(',').find_map(Encoding::from_protocol));
+
+        if let Some(requested) = requested {
+            encoding = requested;
+
+            if let Ok(value) = HeaderValue::from_str(&requested.protocol()) {
+                response.headers_mut().insert(SEC_WEBSOCKET_PROTOCOL, value);
+            }
+        }
+
+        Ok(response)
+    })
+    .await?;
+
+    let (sink, stream) = ws_stream.split();
+
+    Ok(Transport {
+        sink: Box::pin(sink),
+        stream: Box::pin(stream),
+        encoding,
+    })
+}
+
+// ? Every frame is a 4 byte big endian length followed by the payload, there is no text / binary split so
+// ? every frame is decoded with the connection's encoding (JSON until a handshake says otherwise)
+pub fn accept_framed<S>(stream: S) -> Transport
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (sink, stream) = Framed::new(stream, LengthDelimitedCodec::new()).split();
+
+    let sink = sink
+        .sink_map_err(Error::Io)
+        .with(|message: Message| async move { Ok::<_, Error>(Bytes::from(message.into_data())) });
+
+    let stream = stream
+        .map_ok(|frame| Message::Binary(frame.to_vec()))
+        .map_err(Error::Io);
+
+    Transport {
+        sink: Box::pin(sink),
+        stream: Box::pin(stream),
+        encoding: Encoding::Json,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::accept_framed;
+    use futures_util::{SinkExt, StreamExt};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio_tungstenite::tungstenite::Message;
+
+    #[tokio::test]
+    async fn framed_messages_round_trip() {
+        let (mut client, server) = tokio::io::duplex(1024);
+        let mut transport = accept_framed(server);
+
+        // ? Two frames in one write still come out as two messages
+        client.write_all(b"\0\0\0\x05hello\0\0\0\x00").await.unwrap();
+
+        assert_eq!(transport.stream.next().await.unwrap().unwrap(), Message::Binary(b"hello".to_vec()));
+        assert_eq!(transport.stream.next().await.unwrap().unwrap(), Message::Binary(Vec::new()));
+
+        // ? Text and binary messages are both sent as a plain frame
+        transport.sink.send(Message::Text("hi".to_string())).await.unwrap();
+        transport.sink.send(Message::Binary(vec![0xff])).await.unwrap();
+
+        let mut sent = [0; 11];
+
+        client.read_exact(&mut sent).await.unwrap();
+
+        assert_eq!(&sent, b"\0\0\0\x02hi\0\0\0\x01\xff");
+
+        // ? The stream ends when the client closes its side
+        drop(client);
+
+        assert!(transport.stream.next().await.is_none());
+    }
+}
@@ -0,0 +1,562 @@
+use indexmap::IndexMap;
+use serde::Serialize;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, OnceLock,
+};
+use tokio::sync::Mutex;
+
+use crate::{
+    config::AuditLogConfig,
+    error,
+    state::ClientState,
+    structs::common::Value,
+    util::{ndjson::NdjsonWriter, session::ScyllaSession},
+};
+
+static AUDIT_LOG: OnceLock<NdjsonWriter> = OnceLock::new();
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+pub fn init(config: &AuditLogConfig) -> error::Result<()> {
+    let _ = AUDIT_LOG.set(NdjsonWriter::open(&config.path, &config.rotation)?);
+
+    Ok(())
+}
+
+pub fn enabled() -> bool {
+    AUDIT_LOG.get().is_some()
+}
+
+// ? Just enough of CQL to find what a raw statement touches, comments are dropped and literals are kept whole
+#[derive(Debug, PartialEq)]
+enum Token {
+    Word(String),   // ? Keywords, unquoted identifiers, numbers, uuids, ...
+    Quoted(String), // ? "Quoted" identifiers, these are case sensitive
+    Str(String),    // ? 'string' and $$string$$ literals
+    Marker,         // ? A ? bind marker
+    Symbol(char),
+}
+
+fn tokenize(cql: &str) -> Vec<Token> {
+    let chars = cql.chars().collect::<Vec<char>>();
+    let mut tokens = Vec::new();
+    let mut index = 0;
+
+    while let Some(&c) = chars.get(index) {
+        let next = chars.get(index + 1).copied();
+
+        match c {
+            _ if c.is_whitespace() => index += 1,
+            '-' if next == Some('-') => index = find(&chars, index, "\n").unwrap_or(chars.len()),
+            '/' if next == Some('/') => index = find(&chars, index, "\n").unwrap_or(chars.len()),
+            '/' if next == Some('*') => index = find(&chars, index + 2, "*/").map_or(chars.len(), |end| end + 2),
+            '$' if next == Some('$') => {
+                let end = find(&chars, index + 2, "$$").unwrap_or(chars.len());
+
+                tokens.push(Token::Str(chars[index + 2..end].iter().collect()));
+                index = end + 2;
+            }
+            '\'' | '"' => {
+                let mut text = String::new();
+
+                index += 1;
+
+                // ? A doubled quote is an escaped quote
+                while let Some(&d) = chars.get(index) {
+                    index += 1;
+
+                    if d != c {
+                        text.push(d);
+                    } else if chars.get(index) == Some(&c) {
+                        text.push(c);
+                        index += 1;
+                    } else {
+                        break;
+                    }
+                }
+
+                tokens.push(if c == '\'' { Token::Str(text) } else { Token::Quoted(text) });
+            }
+            '?' => {
+                tokens.push(Token::Marker);
+                index += 1;
+            }
+            _ if c.is_alphanumeric() || c == '_' => {
+                let start = index;
+
+                // ? Numbers, uuids and blobs (e.g 0xcafe) are one word
+                let numeric = c.is_ascii_digit();
+
+                while chars
+                    .get(index)
+                    .is_some_and(|&d| d.is_alphanumeric() || d == '_' || (numeric && matches!(d, '.' | '-' | '+')))
+                {
+                    index += 1;
+                }
+
+                tokens.push(Token::Word(chars[start..index].iter().collect()));
+            }
+            _ => {
+                tokens.push(Token::Symbol(c));
+                index += 1;
+            }
+        }
+    }
+
+    tokens
+}
+
+fn find(chars: &[char], from: usize, pattern: &str) -> Option<usize> {
+    let pattern = pattern.chars().collect::<Vec<char>>();
+
+    (from..chars.len()).find(|&index| chars[index..].starts_with(&pattern))
+}
+
+fn keyword(tokens: &[Token], index: usize) -> String {
+    match tokens.get(index) {
+        Some(Token::Word(word)) => word.to_uppercase(),
+        _ => String::new(),
+    }
+}
+
+fn position(tokens: &[Token], word: &str) -> Option<usize> {
+    tokens
+        .iter()
+        .position(|token| matches!(token, Token::Word(w) if w.eq_ignore_ascii_case(word)))
+}
+
+// ? Unquoted identifiers are case insensitive, Scylla keeps them lowercase
+fn identifier(token: Option<&Token>) -> Option<String> {
+    match token? {
+        Token::Word(word) => Some(word.to_lowercase()),
+        Token::Quoted(name) => Some(name.clone()),
+        _ => None,
+    }
+}
+
+// ? keyspace.name or just name
+fn qualified_name(tokens: &[Token], index: usize) -> Option<(Option<String>, String)> {
+    let first = identifier(tokens.get(index))?;
+
+    match tokens.get(index + 1) {
+        Some(Token::Symbol('.')) => Some((Some(first), identifier(tokens.get(index + 2))?)),
+        _ => Some((None, first)),
+    }
+}
+
+// ? Skips IF EXISTS / IF NOT EXISTS
+fn skip_if_exists(tokens: &[Token], mut index: usize) -> usize {
+    if keyword(tokens, index) == "IF" {
+        index += 1;
+
+        if keyword(tokens, index) == "NOT" {
+            index += 1;
+        }
+
+        if keyword(tokens, index) == "EXISTS" {
+            index += 1;
+        }
+    }
+
+    index
+}
+
+// ? What kind of change a raw query makes, None for anything that doesn't modify data (e.g SELECT)
+pub fn classify(cql: &str) -> Option<&'static str> {
+    match keyword(&tokenize(cql), 0).as_str() {
+        "INSERT" => Some("insert"),
+        "UPDATE" => Some("update"),
+        "DELETE" => Some("delete"),
+        "BEGIN" | "APPLY" => Some("batch"),
+        "CREATE" | "ALTER" | "DROP" | "TRUNCATE" | "GRANT" | "REVOKE" => Some("ddl"),
+        _ => None,
+    }
+}
+
+// ? What a statement changes, the keyspace is None if it's in the connection's keyspace and the table is None for
+// ? keyspace level statements. For types, functions and so on the table is the name of the type / function
+#[derive(Debug, Default, PartialEq)]
+pub struct Target {
+    pub keyspace: Option<String>,
+    pub table: Option<String>,
+}
+
+// ? Best effort, None for batches (they can touch any number of tables) and anything we don't recognize
+pub fn target_of(cql: &str) -> Option<Target> {
+    let tokens = tokenize(cql);
+
+    let table = |index: usize| {
+        qualified_name(&tokens, index).map(|(keyspace, table)| Target {
+            keyspace,
+            table: Some(table),
+        })
+    };
+    let keyspace = |index: usize| {
+        identifier(tokens.get(index)).map(|keyspace| Target {
+            keyspace: Some(keyspace),
+            table: None,
+        })
+    };
+
+    match keyword(&tokens, 0).as_str() {
+        "INSERT" => table(2),
+        "UPDATE" => table(1),
+        "DELETE" => table(position(&tokens, "FROM")? + 1),
+        "TRUNCATE" => match keyword(&tokens, 1).as_str() {
+            "TABLE" | "COLUMNFAMILY" => table(2),
+            _ => table(1),
+        },
+        statement @ ("CREATE" | "ALTER" | "DROP") => {
+            let mut index = 1;
+
+            // ? CREATE OR REPLACE FUNCTION, CREATE CUSTOM INDEX
+            if keyword(&tokens, index) == "OR" {
+                index += 2;
+            }
+
+            if keyword(&tokens, index) == "CUSTOM" {
+                index += 1;
+            }
+
+            match keyword(&tokens, index).as_str() {
+                "KEYSPACE" | "SCHEMA" => keyspace(skip_if_exists(&tokens, index + 1)),
+                "TABLE" | "COLUMNFAMILY" | "TYPE" | "FUNCTION" | "AGGREGATE" => table(skip_if_exists(&tokens, index + 1)),
+                "MATERIALIZED" => table(skip_if_exists(&tokens, index + 2)),
+                "INDEX" if statement == "CREATE" => table(position(&tokens, "ON")? + 1),
+                "INDEX" => qualified_name(&tokens, skip_if_exists(&tokens, index + 1))
+                    .map(|(keyspace, _)| Target { keyspace, table: None }),
+                _ => None,
+            }
+        }
+        "GRANT" | "REVOKE" => {
+            let on = position(&tokens, "ON")?;
+
+            match keyword(&tokens, on + 1).as_str() {
+                "KEYSPACE" => keyspace(on + 2),
+                "TABLE" => table(on + 2),
+                "ALL" | "ROLE" | "FUNCTION" | "FUNCTIONS" | "MBEAN" | "MBEANS" => None,
+                _ => table(on + 1),
+            }
+        }
+        _ => None,
+    }
+}
+
+// ? The value a literal or a bind marker stands for, markers are counted from the start of the statement
+fn literal(tokens: &[Token], index: usize, values: &[Value]) -> Option<Value> {
+    match tokens.get(index)? {
+        Token::Marker => values
+            .get(tokens[..index].iter().filter(|token| **token == Token::Marker).count())
+            .cloned(),
+        Token::Str(text) => Some(Value::Str(text.clone())),
+        Token::Symbol('-') => match literal(tokens, index + 1, values)? {
+            // ? A bound i32::MIN / i64::MIN has no positive counterpart
+            Value::Num(number) => number.checked_neg().map(Value::Num),
+            Value::BigInt(number) => number.checked_neg().map(Value::BigInt),
+            _ => None,
+        },
+        Token::Word(word) => Some(match word.to_lowercase().as_str() {
+            "true" => Value::Bool(true),
+            "false" => Value::Bool(false),
+            "null" => Value::Null,
+            _ => match (word.parse::<i32>(), word.parse::<i64>()) {
+                (Ok(number), _) => Value::Num(number),
+                (_, Ok(number)) => Value::BigInt(number),
+                _ => Value::Str(word.clone()),
+            },
+        }),
+        _ => None,
+    }
+}
+
+// ? The columns a raw INSERT sets, or the `column = value` / `column IN (...)` conditions of an UPDATE / DELETE,
+// ? primary_key then picks the key columns out of them. Named bind markers (:name) are skipped
+pub fn statement_values(cql: &str, values: &[Value]) -> IndexMap<String, Value> {
+    let tokens = tokenize(cql);
+    let mut columns = IndexMap::new();
+
+    match keyword(&tokens, 0).as_str() {
+        "INSERT" => {
+            let open = match tokens.iter().position(|token| *token == Token::Symbol('(')) {
+                Some(open) => open,
+                None => return columns,
+            };
+            let names = tokens[open + 1..]
+                .iter()
+                .take_while(|token| **token != Token::Symbol(')'))
+                .filter_map(|token| identifier(Some(token)))
+                .collect::<Vec<String>>();
+
+            let values_at = match position(&tokens, "VALUES") {
+                Some(index) if tokens.get(index + 1) == Some(&Token::Symbol('(')) => index + 2,
+                _ => return columns,
+            };
+
+            let mut index = values_at;
+
+            for name in names {
+                if let Some(value) = literal(&tokens, index, values) {
+                    columns.insert(name, value);
+                }
+
+                // ? On to the next top level comma, values can be collections or function calls
+                let mut depth = 0;
+
+                while let Some(token) = tokens.get(index) {
+                    match token {
+                        Token::Symbol('(' | '[' | '{') => depth += 1,
+                        Token::Symbol(')' | ']' | '}') if depth == 0 => return columns,
+                        Token::Symbol(')' | ']' | '}') => depth -= 1,
+                        Token::Symbol(',') if depth == 0 => break,
+                        _ => {}
+                    }
+
+                    index += 1;
+                }
+
+                index += 1;
+            }
+        }
+        "UPDATE" | "DELETE" => {
+            let mut index = match position(&tokens, "WHERE") {
+                Some(index) => index + 1,
+                None => return columns,
+            };
+
+            while index < tokens.len() && keyword(&tokens, index) != "IF" && tokens[index] != Token::Symbol(';') {
+                let column = identifier(tokens.get(index)).filter(|_| index == 0 || tokens[index - 1] != Token::Symbol('.'));
+
+                if let Some(column) = column {
+                    if tokens.get(index + 1) == Some(&Token::Symbol('=')) {
+                        if let Some(value) = literal(&tokens, index + 2, values) {
+                            columns.insert(column, value);
+                        }
+                    } else if keyword(&tokens, index + 1) == "IN" && tokens.get(index + 2) == Some(&Token::Symbol('(')) {
+                        let mut list = Vec::new();
+                        let mut at = index + 3;
+
+                        while let Some(value) = literal(&tokens, at, values) {
+                            list.push(value);
+
+                            if tokens.get(at + 1) != Some(&Token::Symbol(',')) {
+                                break;
+                            }
+
+                            at += 2;
+                        }
+
+                        columns.insert(column, Value::Array(list));
+                    }
+                }
+
+                index += 1;
+            }
+        }
+        _ => {}
+    }
+
+    columns
+}
+
+// ? The primary key columns of the table according to the cluster's schema, with the values the client sent for them
+pub fn primary_key(
+    session: &ScyllaSession,
+    keyspace: &str,
+    table: &str,
+    columns: &IndexMap<String, Value>,
+) -> Option<IndexMap<String, Value>> {
+    let cluster_data = session.session.get_cluster_data();
+    let table = cluster_data.get_keyspace_info().get(keyspace)?.tables.get(table)?;
+
+    Some(
+        table
+            .partition_key
+            .iter()
+            .chain(table.clustering_key.iter())
+            .map(|column| (column.to_string(), columns.get(column).cloned().unwrap_or(Value::Null)))
+            .collect(),
+    )
+}
+
+pub struct AuditEvent<'a> {
+    pub command: &'a str,
+    pub kind: &'a str,
+    pub nonce: Option<&'a str>,
+    pub keyspace: &'a str,
+    pub table: Option<&'a str>,
+    pub primary_key: Option<IndexMap<String, Value>>,
+    pub cql: &'a str,
+}
+
+#[derive(Serialize)]
+struct AuditRecord<'a> {
+    timestamp: String,
+    id: u64, // ? The attempted record and the outcome of the same statement share the id
+    connection: String,
+    peer: String,
+    #[serde(rename = "commonName")]
+    common_name: Option<String>,
+    fingerprint: Option<String>,
+    command: &'a str,
+    kind: &'a str,
+    nonce: Option<&'a str>,
+    keyspace: &'a str,
+    table: Option<&'a str>,
+    #[serde(rename = "primaryKey")]
+    primary_key: Option<IndexMap<String, Value>>,
+    cql: &'a str,
+    outcome: &'static str, // ? "attempted", then "success" or "error"
+    error: Option<String>,
+}
+
+// ? Returned by attempt, the statement's outcome is written with finish
+pub struct Attempt<'a> {
+    record: AuditRecord<'a>,
+}
+
+// ? Written before the statement is executed, so a statement that gets cancelled (or the server going down while it
+// ? runs) still leaves an attempted record behind
+pub async fn attempt<'a>(user: &Arc<Mutex<ClientState>>, event: AuditEvent<'a>) -> Option<Attempt<'a>> {
+    let writer = AUDIT_LOG.get()?;
+
+    let (connection, peer, identity) = {
+        let user = user.lock().await;
+
+        (user.id.clone(), user.peer.clone(), user.identity.clone())
+    };
+
+    let record = AuditRecord {
+        timestamp: chrono::Utc::now().to_rfc3339(),
+        id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+        connection,
+        peer,
+        common_name: identity.as_ref().and_then(|identity| identity.common_name.clone()),
+        fingerprint: identity.map(|identity| identity.fingerprint),
+        command: event.command,
+        kind: event.kind,
+        nonce: event.nonce,
+        keyspace: event.keyspace,
+        table: event.table,
+        primary_key: event.primary_key,
+        cql: event.cql,
+        outcome: "attempted",
+        error: None,
+    };
+
+    writer.write(&record).await;
+
+    Some(Attempt { record })
+}
+
+impl Attempt<'_> {
+    pub async fn finish(mut self, error: Option<String>) {
+        let writer = match AUDIT_LOG.get() {
+            Some(writer) => writer,
+            None => return,
+        };
+
+        self.record.timestamp = chrono::Utc::now().to_rfc3339();
+        self.record.outcome = if error.is_none() { "success" } else { "error" };
+        self.record.error = error;
+
+        writer.write(&self.record).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{classify, statement_values, target_of, Target};
+    use crate::structs::common::Value;
+
+    fn target(keyspace: Option<&str>, table: Option<&str>) -> Option<Target> {
+        Some(Target {
+            keyspace: keyspace.map(str::to_string),
+            table: table.map(str::to_string),
+        })
+    }
+
+    #[test]
+    fn classifies_statements() {
+        assert_eq!(classify("insert into t (id) values (1)"), Some("insert"));
+        assert_eq!(classify("  UPDATE t SET a = 1 WHERE id = 1"), Some("update"));
+        assert_eq!(classify("DELETE FROM t WHERE id = 1"), Some("delete"));
+        assert_eq!(classify("BEGIN BATCH INSERT INTO t (id) VALUES (1); APPLY BATCH"), Some("batch"));
+        assert_eq!(classify("CREATE TABLE t (id int PRIMARY KEY)"), Some("ddl"));
+        assert_eq!(classify("GRANT SELECT ON KEYSPACE ks TO role"), Some("ddl"));
+        assert_eq!(classify("SELECT * FROM t"), None);
+        assert_eq!(classify(""), None);
+    }
+
+    #[test]
+    fn classifies_past_comments() {
+        assert_eq!(classify("-- cleanup\nDELETE FROM t WHERE id = 1"), Some("delete"));
+        assert_eq!(classify("// cleanup\n  DROP TABLE t"), Some("ddl"));
+        assert_eq!(classify("/* SELECT */ UPDATE t SET a = 1 WHERE id = 1"), Some("update"));
+        assert_eq!(classify("/* DELETE */ SELECT * FROM t"), None);
+        assert_eq!(classify("/* never closed DELETE FROM t"), None);
+    }
+
+    #[test]
+    fn finds_the_target_of_dml() {
+        assert_eq!(target_of("INSERT INTO ks.t (id) VALUES (1)"), target(Some("ks"), Some("t")));
+        assert_eq!(target_of("INSERT INTO t(id) VALUES (1)"), target(None, Some("t")));
+        assert_eq!(target_of("UPDATE Ks.\"MyTable\" SET a = 1 WHERE id = 1"), target(Some("ks"), Some("MyTable")));
+        assert_eq!(target_of("DELETE a, b FROM t WHERE id = 1"), target(None, Some("t")));
+        assert_eq!(target_of("TRUNCATE TABLE ks.t;"), target(Some("ks"), Some("t")));
+        assert_eq!(target_of("TRUNCATE t"), target(None, Some("t")));
+        assert_eq!(target_of("/* ks.other */ INSERT INTO ks.t (id) VALUES (1)"), target(Some("ks"), Some("t")));
+        assert_eq!(target_of("BEGIN BATCH INSERT INTO t (id) VALUES (1); APPLY BATCH"), None);
+        assert_eq!(target_of("SELECT * FROM t"), None);
+    }
+
+    #[test]
+    fn finds_the_target_of_ddl() {
+        assert_eq!(target_of("CREATE KEYSPACE IF NOT EXISTS ks WITH replication = {}"), target(Some("ks"), None));
+        assert_eq!(target_of("DROP KEYSPACE ks"), target(Some("ks"), None));
+        assert_eq!(target_of("CREATE TABLE IF NOT EXISTS ks.t (id int PRIMARY KEY)"), target(Some("ks"), Some("t")));
+        assert_eq!(target_of("ALTER TABLE t ADD a int"), target(None, Some("t")));
+        assert_eq!(target_of("DROP TABLE IF EXISTS ks.t"), target(Some("ks"), Some("t")));
+        assert_eq!(target_of("CREATE MATERIALIZED VIEW ks.v AS SELECT * FROM ks.t"), target(Some("ks"), Some("v")));
+        assert_eq!(target_of("CREATE INDEX IF NOT EXISTS idx ON ks.t (a)"), target(Some("ks"), Some("t")));
+        assert_eq!(target_of("DROP INDEX ks.idx"), target(Some("ks"), None));
+        assert_eq!(target_of("CREATE OR REPLACE FUNCTION ks.f (a int) RETURNS NULL ON NULL INPUT"), target(Some("ks"), Some("f")));
+        assert_eq!(target_of("GRANT SELECT ON KEYSPACE ks TO role"), target(Some("ks"), None));
+        assert_eq!(target_of("REVOKE MODIFY ON ks.t FROM role"), target(Some("ks"), Some("t")));
+        assert_eq!(target_of("GRANT SELECT ON ALL KEYSPACES TO role"), None);
+        assert_eq!(target_of("CREATE ROLE role"), None);
+    }
+
+    #[test]
+    fn finds_inserted_columns() {
+        let values = vec![Value::Str("a".to_string()), Value::Num(2)];
+        let columns = statement_values("INSERT INTO t (id, \"Name\", tags, n) VALUES (?, 'it''s', {'x', 'y'}, ?)", &values);
+
+        assert_eq!(columns.keys().collect::<Vec<_>>(), ["id", "Name", "n"]);
+        assert!(matches!(&columns["id"], Value::Str(v) if v == "a"));
+        assert!(matches!(&columns["Name"], Value::Str(v) if v == "it's"));
+        assert!(matches!(columns["n"], Value::Num(2)));
+    }
+
+    #[test]
+    fn finds_where_conditions() {
+        let values = vec![Value::Num(60), Value::Str("v".to_string()), Value::BigInt(1 << 40), Value::Num(3)];
+        let columns = statement_values(
+            "UPDATE t USING TTL ? SET a = ? WHERE id = ? AND bucket IN (1, ?) AND -- comment ?\n day = -5 IF a = 'x'",
+            &values,
+        );
+
+        assert_eq!(columns.keys().collect::<Vec<_>>(), ["id", "bucket", "day"]);
+        assert!(matches!(columns["id"], Value::BigInt(v) if v == 1 << 40));
+        assert!(matches!(&columns["bucket"], Value::Array(v) if matches!(v[..], [Value::Num(1), Value::Num(3)])));
+        assert!(matches!(columns["day"], Value::Num(-5)));
+
+        let columns = statement_values("DELETE FROM t WHERE a = - ? AND b = - ?", &[Value::Num(i32::MIN), Value::BigInt(i64::MIN)]);
+
+        assert!(columns.is_empty());
+
+        let columns = statement_values("DELETE FROM ks.t WHERE token(id) > 5 AND id = 123e4567-e89b-12d3-a456-426614174000", &[]);
+
+        assert_eq!(columns.keys().collect::<Vec<_>>(), ["id"]);
+        assert!(matches!(&columns["id"], Value::Str(v) if v == "123e4567-e89b-12d3-a456-426614174000"));
+    }
+}
@@ -0,0 +1,160 @@
+use serde::Deserialize;
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::{error::Result, structs::common::Command, util::response::salvage_nonce};
+
+// ? What we use to encode the commands we send and decode the binary frames we get
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Encoding {
+    Json,
+    MessagePack,
+    Cbor,
+}
+
+// ? Used to find the nonce in a binary frame that failed to decode as a command
+#[derive(Deserialize)]
+struct NonceOnly {
+    nonce: Option<String>,
+}
+
+impl Encoding {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "json" => Some(Encoding::Json),
+            "msgpack" | "messagepack" => Some(Encoding::MessagePack),
+            "cbor" => Some(Encoding::Cbor),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encoding::Json => "json",
+            Encoding::MessagePack => "msgpack",
+            Encoding::Cbor => "cbor",
+        }
+    }
+
+    // ? The WebSocket subprotocols we accept, e.g "scyllatcp.msgpack"
+    pub fn from_protocol(protocol: &str) -> Option<Self> {
+        Encoding::from_name(protocol.trim().strip_prefix("scyllatcp.")?)
+    }
+
+    pub fn protocol(&self) -> String {
+        format!("scyllatcp.{}", self.name())
+    }
+
+    pub fn encode(&self, command: &Command) -> Result<Message> {
+        match self {
+            Encoding::Json => Ok(Message::Text(serde_json::to_string(command)?)),
+            Encoding::MessagePack => Ok(Message::Binary(rmp_serde::to_vec_named(command)?)),
+            Encoding::Cbor => {
+                let mut bytes = Vec::new();
+
+                ciborium::into_writer(command, &mut bytes)?;
+
+                Ok(Message::Binary(bytes))
+            }
+        }
+    }
+
+    pub fn decode(&self, data: &[u8]) -> Result<Command> {
+        match self {
+            Encoding::Json => Ok(serde_json::from_slice(data)?),
+            Encoding::MessagePack => Ok(rmp_serde::from_slice(data)?),
+            Encoding::Cbor => Ok(ciborium::from_reader(data)?),
+        }
+    }
+
+    pub fn salvage_nonce(&self, data: &[u8]) -> Option<String> {
+        match self {
+            Encoding::Json => salvage_nonce(&String::from_utf8_lossy(data)),
+            Encoding::MessagePack => rmp_serde::from_slice::<NonceOnly>(data).ok()?.nonce,
+            Encoding::Cbor => ciborium::from_reader::<NonceOnly, _>(data).ok()?.nonce,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use indexmap::IndexMap;
+
+    use super::Encoding;
+    use crate::structs::{
+        common::{Command, CommandData, Value},
+        insert::InsertData,
+    };
+
+    fn insert(columns: Vec<(&str, Value)>) -> Command {
+        Command {
+            hash: String::new(),
+            command: "insert".to_string(),
+            table: Some("events".to_string()),
+            keyspace: Some("app".to_string()),
+            data: CommandData::Insert(InsertData {
+                columns: columns.into_iter().map(|(name, value)| (name.to_string(), value)).collect(),
+                if_not_exists: None,
+            }),
+            length: 0,
+            nonce: Some("n-1".to_string()),
+            profile: None,
+            idempotent: None,
+            retry_policy: None,
+            trace: None,
+            stats: None,
+            received_at: None,
+        }
+    }
+
+    fn round_trip(encoding: Encoding, command: &Command) -> IndexMap<String, Value> {
+        let data = encoding.encode(command).unwrap().into_data();
+        let decoded = encoding.decode(&data).unwrap();
+
+        assert_eq!(decoded.nonce.as_deref(), Some("n-1"));
+
+        match decoded.data {
+            CommandData::Insert(insert) => insert.columns,
+            data => panic!("Decoded as {:?}", data),
+        }
+    }
+
+    #[test]
+    fn binary_encodings_keep_blobs_and_bigints() {
+        let command = insert(vec![
+            ("raw", Value::Blob(vec![0, 159, 146, 150, 255])),
+            ("utf8", Value::Blob(b"hello".to_vec())),
+            ("big", Value::BigInt(i64::MAX)),
+            ("negative", Value::BigInt(-(1 << 40))),
+            ("small", Value::Num(42)),
+            ("name", Value::Str("hello".to_string())),
+        ]);
+
+        for encoding in [Encoding::MessagePack, Encoding::Cbor] {
+            let columns = round_trip(encoding, &command);
+
+            assert!(matches!(&columns["raw"], Value::Blob(v) if v == &[0, 159, 146, 150, 255]), "{:?}", encoding);
+            assert!(matches!(&columns["utf8"], Value::Blob(v) if v == b"hello"), "{:?}", encoding);
+            assert!(matches!(columns["big"], Value::BigInt(i64::MAX)), "{:?}", encoding);
+            assert!(matches!(columns["negative"], Value::BigInt(v) if v == -(1 << 40)), "{:?}", encoding);
+            assert!(matches!(columns["small"], Value::Num(42)), "{:?}", encoding);
+            assert!(matches!(&columns["name"], Value::Str(v) if v == "hello"), "{:?}", encoding);
+        }
+    }
+
+    #[test]
+    fn json_sends_blobs_and_bigints_as_strings() {
+        let command = insert(vec![
+            ("raw", Value::Blob(vec![0, 159, 146, 150, 255])),
+            ("utf8", Value::Blob(b"hello".to_vec())),
+            ("big", Value::BigInt(i64::MAX)),
+            ("small", Value::Num(42)),
+        ]);
+
+        let columns = round_trip(Encoding::Json, &command);
+
+        assert!(matches!(&columns["raw"], Value::Str(v) if v == "0x009f9296ff"));
+        assert!(matches!(&columns["utf8"], Value::Str(v) if v == "0x68656c6c6f"));
+        assert!(matches!(&columns["big"], Value::Str(v) if v == &i64::MAX.to_string()));
+        assert!(matches!(columns["small"], Value::Num(42)));
+    }
+}
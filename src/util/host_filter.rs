@@ -0,0 +1,106 @@
+use scylla::transport::{topology::Peer, NodeAddr};
+use std::{
+    collections::HashSet,
+    net::{IpAddr, SocketAddr},
+};
+use tokio::net::lookup_host;
+
+use crate::error::{self, Error};
+
+const DEFAULT_PORT: u16 = 9042;
+
+// ? Only lets the driver open connections to the hosts / racks we were told to use
+pub struct AllowListFilter {
+    local_dc: Option<String>,
+    racks: HashSet<String>,
+    hosts: HashSet<SocketAddr>,
+}
+
+impl AllowListFilter {
+    pub async fn new(local_dc: Option<String>, racks: &[String], hosts: &[String]) -> error::Result<Self> {
+        let mut allowed_hosts = HashSet::new();
+
+        for host in hosts {
+            allowed_hosts.extend(resolve(host).await?);
+        }
+
+        Ok(Self {
+            local_dc,
+            racks: racks.iter().cloned().collect(),
+            hosts: allowed_hosts,
+        })
+    }
+}
+
+// ? Hosts without a port get the default CQL port, IPv6 addresses can be written with or without brackets
+async fn resolve(host: &str) -> error::Result<Vec<SocketAddr>> {
+    if let Ok(addr) = host.parse::<SocketAddr>() {
+        return Ok(vec![addr]);
+    }
+
+    if let Ok(ip) = host.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>() {
+        return Ok(vec![SocketAddr::new(ip, DEFAULT_PORT)]);
+    }
+
+    let resolved = match host.rsplit_once(':') {
+        Some((name, port)) => {
+            let port = port
+                .parse::<u16>()
+                .map_err(|_| Error::InvalidData(format!("Invalid port in allowed host {}", host)))?;
+
+            lookup_host((name, port)).await
+        }
+        None => lookup_host((host, DEFAULT_PORT)).await,
+    };
+
+    resolved
+        .map(|addrs| addrs.collect())
+        .map_err(|e| Error::ConnectFailed(format!("Failed to resolve allowed host {}: {}", host, e)))
+}
+
+impl scylla::host_filter::HostFilter for AllowListFilter {
+    fn accept(&self, peer: &Peer) -> bool {
+        if !self.racks.is_empty() {
+            // ? rack names are usually only unique within a DC, so a rack only counts in the local DC
+            if self.local_dc.is_some() && peer.datacenter != self.local_dc {
+                return false;
+            }
+
+            match &peer.rack {
+                Some(rack) if self.racks.contains(rack) => {}
+                _ => return false,
+            }
+        }
+
+        if !self.hosts.is_empty() {
+            match peer.address {
+                NodeAddr::Translatable(addr) => return self.hosts.contains(&addr),
+                // ? Same as the driver's allow list, untranslatable addresses are always contact points we already accepted
+                _ => return true,
+            }
+        }
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::resolve;
+
+    #[tokio::test]
+    async fn resolves_ip_addresses() {
+        assert_eq!(resolve("10.0.0.1").await.unwrap(), vec!["10.0.0.1:9042".parse().unwrap()]);
+        assert_eq!(resolve("10.0.0.1:19042").await.unwrap(), vec!["10.0.0.1:19042".parse().unwrap()]);
+        assert_eq!(resolve("fe80::1").await.unwrap(), vec!["[fe80::1]:9042".parse().unwrap()]);
+        assert_eq!(resolve("[fe80::1]").await.unwrap(), vec!["[fe80::1]:9042".parse().unwrap()]);
+        assert_eq!(resolve("[fe80::1]:19042").await.unwrap(), vec!["[fe80::1]:19042".parse().unwrap()]);
+    }
+
+    #[tokio::test]
+    async fn resolves_host_names() {
+        assert!(resolve("localhost").await.unwrap().iter().all(|addr| addr.port() == 9042));
+        assert!(resolve("localhost:19042").await.unwrap().iter().all(|addr| addr.port() == 19042));
+        assert!(resolve("localhost:port").await.is_err());
+    }
+}
@@ -1,2 +1,10 @@
+pub mod audit;
+pub mod encoding;
+pub mod host_filter;
+pub mod ndjson;
 pub mod parse_cql_value;
 pub mod queries;
+pub mod response;
+pub mod session;
+pub mod slow_log;
+pub mod statement;
@@ -0,0 +1,139 @@
+use serde::Serialize;
+use std::fs::OpenOptions;
+use tokio::{fs::File, io::AsyncWriteExt, sync::Mutex};
+use tracing::warn;
+
+use crate::{config::RotationConfig, error};
+
+struct OpenFile {
+    file: File,
+    size: u64,
+}
+
+// ? Appends one JSON object per line, used for the logs that are meant to be read by other tools
+pub struct NdjsonWriter {
+    path: String,
+    rotation: RotationConfig,
+    file: Mutex<OpenFile>,
+}
+
+fn open_file(path: &str) -> error::Result<OpenFile> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+
+    let size = file.metadata().map(|metadata| metadata.len()).unwrap_or(0);
+
+    Ok(OpenFile {
+        file: File::from_std(file),
+        size,
+    })
+}
+
+impl NdjsonWriter {
+    pub fn open(path: &str, rotation: &RotationConfig) -> error::Result<Self> {
+        Ok(Self {
+            path: path.to_string(),
+            rotation: rotation.clone(),
+            file: Mutex::new(open_file(path)?),
+        })
+    }
+
+    // ? path.1 is the newest old file, anything past maxFiles is deleted
+    async fn rotate(&self, open: &mut OpenFile) -> error::Result<()> {
+        let _ = open.file.flush().await;
+
+        let max_files = self.rotation.max_files.max(1);
+
+        let _ = tokio::fs::remove_file(format!("{}.{}", self.path, max_files)).await;
+
+        for index in (1..max_files).rev() {
+            let _ = tokio::fs::rename(
+                format!("{}.{}", self.path, index),
+                format!("{}.{}", self.path, index + 1),
+            )
+            .await;
+        }
+
+        tokio::fs::rename(&self.path, format!("{}.1", self.path)).await?;
+
+        *open = open_file(&self.path)?;
+
+        Ok(())
+    }
+
+    pub async fn write<T: Serialize>(&self, record: &T) {
+        let mut line = match serde_json::to_vec(record) {
+            Ok(line) => line,
+            Err(e) => {
+                warn!(path = %self.path, "Failed to serialize a record: {}", e);
+
+                return;
+            }
+        };
+
+        line.push(b'\n');
+
+        // ? The whole line is written while holding the lock, so records from different tasks never interleave
+        let mut open = self.file.lock().await;
+
+        if let Some(max_bytes) = self.rotation.max_bytes {
+            if open.size > 0 && open.size + line.len() as u64 > max_bytes {
+                if let Err(e) = self.rotate(&mut open).await {
+                    warn!(path = %self.path, "Failed to rotate: {}", e);
+                }
+            }
+        }
+
+        // ? tokio only hands the write to a blocking thread, the flush waits for it so a record is never lost on shutdown
+        let written = match open.file.write_all(&line).await {
+            Ok(()) => open.file.flush().await,
+            Err(e) => Err(e),
+        };
+
+        match written {
+            Ok(()) => open.size += line.len() as u64,
+            Err(e) => warn!(path = %self.path, "Failed to write a record: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::NdjsonWriter;
+    use crate::config::RotationConfig;
+    use std::path::Path;
+
+    #[tokio::test]
+    async fn rotates_and_keeps_max_files() {
+        let path = std::env::temp_dir()
+            .join(format!("scyllatcp-rotation-{}.ndjson", std::process::id()))
+            .to_string_lossy()
+            .to_string();
+
+        let rotated = |index: usize| format!("{}.{}", path, index);
+
+        for file in [path.clone(), rotated(1), rotated(2), rotated(3)] {
+            let _ = std::fs::remove_file(file);
+        }
+
+        // ? Every record is 8 bytes with its newline, so each file only fits one
+        let rotation = RotationConfig {
+            max_bytes: Some(10),
+            max_files: 2,
+        };
+
+        let writer = NdjsonWriter::open(&path, &rotation).unwrap();
+
+        for record in ["first", "secnd", "third", "forth"] {
+            writer.write(&record).await;
+        }
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "\"forth\"\n");
+        assert_eq!(std::fs::read_to_string(rotated(1)).unwrap(), "\"third\"\n");
+        assert_eq!(std::fs::read_to_string(rotated(2)).unwrap(), "\"secnd\"\n");
+        assert!(!Path::new(&rotated(3)).exists());
+
+        for file in [path.clone(), rotated(1), rotated(2)] {
+            let _ = std::fs::remove_file(file);
+        }
+    }
+}
@@ -1,81 +1,20 @@
-use std::collections::HashMap;
-
 use scylla::frame::response::result::CqlValue;
+use tracing::{error, warn};
 
 use crate::structs::common::Value;
 
-pub fn parse_cql_non(column: Option<CqlValue>) -> Value {
-    match column {
-        Some(value) => match value {
-            scylla::frame::response::result::CqlValue::Int(num) => {
-                Value::Num(num.to_owned().into())
-            }
-            scylla::frame::response::result::CqlValue::Text(text) => Value::Str(text.to_owned()),
-            scylla::frame::response::result::CqlValue::Boolean(boolean) => {
-                Value::Bool(boolean.to_owned())
-            }
-            scylla::frame::response::result::CqlValue::BigInt(bigint) => {
-                Value::Str(bigint.to_owned().to_string())
-            }
-            scylla::frame::response::result::CqlValue::List(list) => {
-                let mut array = Vec::new();
-
-                for value in list {
-                    array.push(parse_cql_non(Some(value)));
-                }
-
-                Value::Array(array)
-            }
-            scylla::frame::response::result::CqlValue::Empty => Value::Null,
-            scylla::frame::response::result::CqlValue::Blob(blob) => {
-                Value::Str(String::from_utf8(blob.to_owned()).unwrap())
-            }
-            scylla::frame::response::result::CqlValue::Map(decimal) => {
-                let mut map = Vec::new();
-
-                for (key, value) in decimal {
-                    map.push((parse_cql_non(Some(key)), parse_cql_non(Some(value))));
-                }
-
-                Value::Map(map)
-            }
-            scylla::frame::response::result::CqlValue::Timestamp(timestamp) => {
-                let string = timestamp.0.to_owned();
-                let native = chrono::NaiveDateTime::from_timestamp_millis(string);
-                let datetime = chrono::DateTime::<chrono::Utc>::from_naive_utc_and_offset(native.unwrap(), chrono::Utc);
-
-                Value::Date(datetime.to_rfc3339())
-            }
-            // scylla::frame::response::result::CqlValue::UserDefinedType { keyspace, type_name, fields } => {
-            //     let mut hash = HashMap::new();
-
-            //     for field in fields {
-            //         hash.insert(field.0.to_owned(), parse_cql_non(field.1.clone().to_owned()));
-            //     }
-
-            //     Value::Object(hash)
-            // }
-            _ => {
-                println!("[Error] Failed to parse CqlValue: {:?}", value);
-
-                Value::Null
-            }
-        }
-        None => Value::Null,
-}}
-
 pub fn parse_cql_value(column: Option<&CqlValue>) -> Value {
     match column {
         Some(value) => match value {
             scylla::frame::response::result::CqlValue::Int(num) => {
-                Value::Num(num.to_owned().into())
+                Value::Num(num.to_owned())
             }
             scylla::frame::response::result::CqlValue::Text(text) => Value::Str(text.to_owned()),
             scylla::frame::response::result::CqlValue::Boolean(boolean) => {
                 Value::Bool(boolean.to_owned())
             }
             scylla::frame::response::result::CqlValue::BigInt(bigint) => {
-                Value::Str(bigint.to_owned().to_string())
+                Value::BigInt(bigint.to_owned())
             }
             scylla::frame::response::result::CqlValue::List(list) => {
                 let mut array = Vec::new();
@@ -88,7 +27,7 @@ pub fn parse_cql_value(column: Option<&CqlValue>) -> Value {
             }
             scylla::frame::response::result::CqlValue::Empty => Value::Null,
             scylla::frame::response::result::CqlValue::Blob(blob) => {
-                Value::Str(String::from_utf8(blob.to_owned()).unwrap())
+                Value::Blob(blob.to_owned())
             }
             scylla::frame::response::result::CqlValue::Map(decimal) => {
                 let mut map = Vec::new();
@@ -101,10 +40,20 @@ pub fn parse_cql_value(column: Option<&CqlValue>) -> Value {
             }
             scylla::frame::response::result::CqlValue::Timestamp(timestamp) => {
                 let string = timestamp.0.to_owned();
-                let native = chrono::NaiveDateTime::from_timestamp_millis(string);
-                let datetime = chrono::DateTime::<chrono::Utc>::from_naive_utc_and_offset(native.unwrap(), chrono::Utc);
 
-                Value::Date(datetime.to_rfc3339())
+                // ? chrono can't hold every timestamp Scylla can, those are sent as the raw milliseconds instead
+                match chrono::NaiveDateTime::from_timestamp_millis(string) {
+                    Some(native) => {
+                        let datetime = chrono::DateTime::<chrono::Utc>::from_naive_utc_and_offset(native, chrono::Utc);
+
+                        Value::Date(datetime.to_rfc3339())
+                    }
+                    None => {
+                        warn!("Timestamp out of range: {}", string);
+
+                        Value::BigInt(string)
+                    }
+                }
             }
             // scylla::frame::response::result::CqlValue::UserDefinedType { keyspace, type_name, fields } => {
             //     let mut hash = HashMap::new();
@@ -116,7 +65,7 @@ pub fn parse_cql_value(column: Option<&CqlValue>) -> Value {
             //     Value::Object(hash)
             // }
             _ => {
-                println!("[Error] Failed to parse CqlValue: {:?}", value);
+                error!("Failed to parse CqlValue: {:?}", value);
 
                 Value::Null
             }
@@ -1,14 +1,15 @@
+use tracing::debug;
+
 use crate::structs::{common::Value, insert::InsertData, select::SelectData};
 
 pub struct Query<'a> {
     pub query: String,
     pub values: Vec<&'a Value>,
-    pub map: Vec<String>,
 }
 
 pub fn select_query<'a>(
-    keyspace: &'a String,
-    table: &'a String,
+    keyspace: &'a str,
+    table: &'a str,
     data: &'a SelectData,
 ) -> Query<'a> {
     let mut query = String::from("SELECT ");
@@ -23,8 +24,8 @@ pub fn select_query<'a>(
         columns.push(column);
     }
 
-    if columns.len() == 0 {
-        query.push_str("*");
+    if columns.is_empty() {
+        query.push('*');
     }
 
     let column_joinable = columns
@@ -34,11 +35,11 @@ pub fn select_query<'a>(
 
     query.push_str(column_joinable.join(", ").as_str());
     query.push_str(" FROM ");
-    query.push_str(keyspace.as_str());
-    query.push_str(".");
-    query.push_str(table.as_str());
+    query.push_str(keyspace);
+    query.push('.');
+    query.push_str(table);
 
-    if wc.len() > 0 {
+    if !wc.is_empty() {
         query.push_str(" WHERE ");
     }
 
@@ -58,18 +59,17 @@ pub fn select_query<'a>(
         query.push_str(&data.limit.to_string());
     }
 
-    println!("{}", query);
+    debug!("{}", query);
 
     Query {
         query: query.to_string(),
         values,
-        map: column_joinable,
     }
 }
 
 pub fn insert_query<'a>(
-    keyspace: &'a String,
-    table: &'a String,
+    keyspace: &'a str,
+    table: &'a str,
     data: &'a InsertData,
 ) -> Query<'a> {
     let mut query = String::from("");
@@ -82,9 +82,9 @@ pub fn insert_query<'a>(
 
     let mut values: Vec<&Value> = Vec::new();
 
-    query.push_str(keyspace.as_str());
-    query.push_str(".");
-    query.push_str(table.as_str());
+    query.push_str(keyspace);
+    query.push('.');
+    query.push_str(table);
     query.push_str(" (");
 
     let mut columns = Vec::new();
@@ -103,26 +103,21 @@ pub fn insert_query<'a>(
     query.push_str(column_joinable.join(", ").as_str());
     query.push_str(") VALUES (");
 
-    let mut value_placeholders = Vec::new();
-
-    for _ in columns {
-        value_placeholders.push("?");
-    }
+    let value_placeholders = vec!["?"; columns.len()];
 
     query.push_str(&value_placeholders.join(", "));
-    query.push_str(")");
+    query.push(')');
 
     Query {
         query,
         values,
-        map: column_joinable,
     }
 }
 
-pub fn raw_query<'a>(query: &'a String, limit: i32) -> Query<'a> {
+pub fn raw_query<'a>(query: &'a str, values: &'a [Value], limit: i32) -> Query<'a> {
     let mut query = query.to_string();
 
-    let values: Vec<&Value> = Vec::new();
+    let values: Vec<&Value> = values.iter().collect();
 
     if limit > 0 {
         query.push_str(" LIMIT ");
@@ -132,6 +127,5 @@ pub fn raw_query<'a>(query: &'a String, limit: i32) -> Query<'a> {
     Query {
         query,
         values,
-        map: Vec::new(),
     }
 }
@@ -0,0 +1,208 @@
+use futures_util::SinkExt;
+use std::{sync::Arc, time::Instant};
+use tokio::sync::Mutex;
+
+use crate::{
+    calculate_hash::calculate_hash,
+    error::Error,
+    metrics,
+    structs::common::{Command, CommandData, QueryResult, Stats},
+    transport::MessageSink,
+    util::encoding::Encoding,
+};
+
+pub struct Writer {
+    pub sink: Mutex<MessageSink>,
+    pub encoding: Mutex<Encoding>,
+}
+
+impl Writer {
+    pub fn new(sink: MessageSink, encoding: Encoding) -> Self {
+        Self {
+            sink: Mutex::new(sink),
+            encoding: Mutex::new(encoding),
+        }
+    }
+}
+
+pub type Outgoing = Arc<Writer>;
+
+// ? Builds a response with the length and hash filled in, the nonce is always the one from the request
+pub fn build_response(command: &str, data: CommandData, nonce: Option<String>) -> Command {
+    let mut response = Command {
+        hash: "".to_string(),
+        length: 0,
+        command: command.to_string(),
+        keyspace: None,
+        table: None,
+        data,
+        nonce,
+        profile: None,
+        idempotent: None,
+        retry_policy: None,
+        trace: None,
+        stats: None,
+        received_at: None,
+    };
+
+    seal(&mut response);
+
+    response
+}
+
+// ? Has to run again whenever the data changes
+fn seal(response: &mut Command) {
+    let string_data = serde_json::to_string(&response.data).unwrap_or_default();
+
+    response.length = string_data.len() + response.command.len();
+
+    response.hash = calculate_hash(
+        response.command.to_string() + &response.length.to_string() + &string_data,
+    );
+}
+
+// ? Returns the size of the encoded response
+pub async fn send_response(write: &Outgoing, command: &str, data: CommandData, nonce: Option<String>) -> usize {
+    send_response_with_stats(write, command, data, nonce, None).await
+}
+
+// ? The response is encoded once without the stats to measure it, the stats are then added and it's encoded again
+pub async fn send_response_with_stats(
+    write: &Outgoing,
+    command: &str,
+    data: CommandData,
+    nonce: Option<String>,
+    stats: Option<Stats>,
+) -> usize {
+    let mut response = build_response(command, data, nonce);
+    let encoding = *write.encoding.lock().await;
+
+    if let Some(mut stats) = stats {
+        let started = Instant::now();
+
+        stats.bytes = encoding.encode(&response).map(|message| message.len()).unwrap_or_default();
+        stats.serialization_ms += started.elapsed().as_secs_f64() * 1000.0;
+
+        response.data.set_stats(stats);
+
+        seal(&mut response);
+    }
+
+    match encoding.encode(&response) {
+        Ok(message) => {
+            let size = message.len();
+
+            metrics::BYTES_SENT.inc_by(size as u64);
+
+            // ? we don't care about if it succeeds or not
+            let _ = write.sink.lock().await.send(message).await;
+
+            size
+        }
+        Err(_) => 0,
+    }
+}
+
+// ? code is only used for the metrics, e.g "not_connected" or "invalid_data"
+async fn send_error(write: &Outgoing, code: &str, error: &str, nonce: Option<String>) {
+    metrics::record_error(code);
+
+    send_response(
+        write,
+        "error",
+        CommandData::SelectResponse(QueryResult {
+            error: Some(error.to_string()),
+            result: Vec::new(),
+            warnings: Vec::new(),
+            execution: None,
+            tracing: None,
+            stats: None,
+        }),
+        nonce,
+    )
+    .await;
+}
+
+// ? Every error reply goes through here, so the code in the metrics always matches the error
+pub async fn send_failure(write: &Outgoing, error: &Error, nonce: Option<String>) {
+    send_error(write, error.code(), &error.to_string(), nonce).await;
+}
+
+pub async fn close(write: &Outgoing) {
+    let _ = write.sink.lock().await.close().await;
+}
+
+// ? When the command fails to parse we still try to find the nonce so the client can resolve the request
+pub fn salvage_nonce(text: &str) -> Option<String> {
+    if let Ok(serde_json::Value::Object(map)) = serde_json::from_str::<serde_json::Value>(text) {
+        return match map.get("nonce") {
+            Some(serde_json::Value::String(nonce)) => Some(nonce.to_string()),
+            _ => None,
+        };
+    }
+
+    // ? The JSON is malformed (or cut off), so we scan for "nonce": "..." by hand
+    let start = text.find("\"nonce\"")? + "\"nonce\"".len();
+    let rest = text[start..].trim_start().strip_prefix(':')?.trim_start();
+    let rest = rest.strip_prefix('"')?;
+
+    let mut nonce = String::new();
+    let mut chars = rest.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => return Some(nonce),
+            '\\' => nonce.push(match chars.next()? {
+                'n' => '\n',
+                'r' => '\r',
+                't' => '\t',
+                'b' => '\u{8}',
+                'f' => '\u{c}',
+                'u' => {
+                    let hex: String = chars.by_ref().take(4).collect();
+
+                    if hex.len() != 4 {
+                        return None;
+                    }
+
+                    char::from_u32(u32::from_str_radix(&hex, 16).ok()?)?
+                }
+                c => c, // ? \" \\ and \/
+            }),
+            _ => nonce.push(c),
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::salvage_nonce;
+
+    #[test]
+    fn salvages_from_valid_json() {
+        assert_eq!(salvage_nonce(r#"{"command":"select","nonce":"abc"}"#), Some("abc".to_string()));
+        assert_eq!(salvage_nonce(r#"{"command":"select","nonce":1}"#), None);
+        assert_eq!(salvage_nonce(r#"{"command":"select"}"#), None);
+    }
+
+    #[test]
+    fn salvages_from_truncated_json() {
+        assert_eq!(
+            salvage_nonce(r#"{"command":"select","nonce" : "abc","data":{"keyspace":"#),
+            Some("abc".to_string())
+        );
+        assert_eq!(salvage_nonce(r#"{"command":"select","nonce":"ab"#), None);
+        assert_eq!(salvage_nonce(r#"{"command":"select","nonce":"#), None);
+        assert_eq!(salvage_nonce(r#"{"command":"select","nonce""#), None);
+        assert_eq!(salvage_nonce(r#"{"command":"select","nonce":"a\"#), None);
+    }
+
+    #[test]
+    fn salvages_escaped_nonces() {
+        assert_eq!(salvage_nonce(r#"{"nonce":"a\"b\\c"}"#), Some("a\"b\\c".to_string()));
+        assert_eq!(salvage_nonce(r#"{"nonce":"a\"b\\c\n\u00e9","data":"#), Some("a\"b\\c\né".to_string()));
+        assert_eq!(salvage_nonce(r#"{"nonce":"\u00"#), None);
+    }
+}
@@ -0,0 +1,330 @@
+use scylla::{
+    execution_profile::ExecutionProfileHandle,
+    load_balancing::DefaultPolicy,
+    retry_policy::{DefaultRetryPolicy, FallthroughRetryPolicy, RetryPolicy},
+    speculative_execution::{
+        PercentileSpeculativeExecutionPolicy, SimpleSpeculativeExecutionPolicy,
+        SpeculativeExecutionPolicy,
+    },
+    statement::{Consistency, SerialConsistency},
+    transport::session::PoolSize,
+    transport::downgrading_consistency_retry_policy::DowngradingConsistencyRetryPolicy,
+    transport::Compression,
+    ExecutionProfile, Session, SessionBuilder,
+};
+use std::{collections::HashMap, num::NonZeroUsize, sync::Arc, time::Duration};
+
+use crate::{
+    calculate_hash::calculate_hash,
+    error::{self, Error},
+    structs::connect::{
+        ConnectData, ExecutionProfileOptions, SessionOptions, SpeculativeExecutionOptions,
+    },
+    tls::build_cluster_context,
+    util::host_filter::AllowListFilter,
+};
+
+fn invalid(message: &str) -> Error {
+    Error::InvalidData(message.to_string())
+}
+
+pub fn parse_consistency(consistency: &str) -> error::Result<Consistency> {
+    match consistency.to_lowercase().replace(['_', '-'], "").as_str() {
+        "any" => Ok(Consistency::Any),
+        "one" => Ok(Consistency::One),
+        "two" => Ok(Consistency::Two),
+        "three" => Ok(Consistency::Three),
+        "quorum" => Ok(Consistency::Quorum),
+        "all" => Ok(Consistency::All),
+        "localquorum" => Ok(Consistency::LocalQuorum),
+        "eachquorum" => Ok(Consistency::EachQuorum),
+        "localone" => Ok(Consistency::LocalOne),
+        "serial" => Ok(Consistency::Serial),
+        "localserial" => Ok(Consistency::LocalSerial),
+        _ => Err(Error::InvalidData(format!("Unknown consistency: {}", consistency))),
+    }
+}
+
+fn parse_serial_consistency(serial_consistency: &str) -> error::Result<SerialConsistency> {
+    match serial_consistency.to_lowercase().replace(['_', '-'], "").as_str() {
+        "serial" => Ok(SerialConsistency::Serial),
+        "localserial" => Ok(SerialConsistency::LocalSerial),
+        _ => Err(Error::InvalidData(format!("Unknown serial consistency: {}", serial_consistency))),
+    }
+}
+
+// ? "none" never retries, "default" is the driver's policy and "downgrading" retries with a lower consistency
+pub fn parse_retry_policy(retry_policy: &str) -> error::Result<Box<dyn RetryPolicy>> {
+    match retry_policy.to_lowercase().replace(['_', '-'], "").as_str() {
+        "none" | "fallthrough" => Ok(Box::new(FallthroughRetryPolicy::new())),
+        "default" => Ok(Box::new(DefaultRetryPolicy::new())),
+        "downgrading" | "downgradingconsistency" => Ok(Box::new(DowngradingConsistencyRetryPolicy::new())),
+        _ => Err(Error::InvalidData(format!("Unknown retry policy: {}", retry_policy))),
+    }
+}
+
+fn build_speculative_execution(
+    options: &SpeculativeExecutionOptions,
+) -> error::Result<Arc<dyn SpeculativeExecutionPolicy>> {
+    match options.policy.to_lowercase().as_str() {
+        "simple" => Ok(Arc::new(SimpleSpeculativeExecutionPolicy {
+            max_retry_count: options.max_retry_count,
+            retry_interval: Duration::from_millis(
+                options
+                    .retry_interval
+                    .ok_or_else(|| invalid("retryInterval is required for simple speculative execution"))?,
+            ),
+        })),
+        "percentile" => {
+            let percentile = options
+                .percentile
+                .ok_or_else(|| invalid("percentile is required for percentile speculative execution"))?;
+
+            if !(0.0..100.0).contains(&percentile) {
+                return Err(invalid("percentile has to be between 0 and 100"));
+            }
+
+            Ok(Arc::new(PercentileSpeculativeExecutionPolicy {
+                max_retry_count: options.max_retry_count,
+                percentile,
+            }))
+        }
+        _ => Err(Error::InvalidData(format!("Unknown speculative execution policy: {}", options.policy))),
+    }
+}
+
+fn parse_compression(compression: &str) -> error::Result<Option<Compression>> {
+    match compression.to_lowercase().as_str() {
+        "lz4" => Ok(Some(Compression::Lz4)),
+        "snappy" => Ok(Some(Compression::Snappy)),
+        "none" | "" => Ok(None),
+        _ => Err(Error::InvalidData(format!("Unknown compression: {}", compression))),
+    }
+}
+
+// ? Everything that changes the session goes into the key, the keyspace doesn't since we always send it with the query
+pub fn session_key(connect_data: &ConnectData) -> String {
+    let mut connect_data = connect_data.clone();
+
+    connect_data.keyspace = String::new();
+
+    calculate_hash(serde_json::to_string(&connect_data).unwrap_or_default())
+}
+
+// ? The session together with the execution profiles commands can pick by name
+#[derive(Debug)]
+pub struct ScyllaSession {
+    pub session: Session,
+    pub profiles: HashMap<String, ExecutionProfileHandle>,
+}
+
+// ? Anything the profile doesn't set comes from the connect data / session options
+fn build_execution_profile(
+    connect_data: &ConnectData,
+    options: &SessionOptions,
+    overrides: &ExecutionProfileOptions,
+) -> error::Result<ExecutionProfile> {
+    let local_dc = overrides
+        .local_data_center
+        .as_deref()
+        .unwrap_or(&connect_data.local_data_center);
+
+    // ? Token aware + prefer the local DC, we don't fail over to other DC's unless asked to
+    let mut policy = DefaultPolicy::builder()
+        .token_aware(overrides.token_aware.unwrap_or(true))
+        .permit_dc_failover(
+            overrides
+                .permit_dc_failover
+                .or(connect_data.permit_dc_failover)
+                .unwrap_or(false),
+        );
+
+    if !local_dc.is_empty() {
+        policy = policy.prefer_datacenter(local_dc.to_string());
+    }
+
+    let mut profile = ExecutionProfile::builder().load_balancing_policy(policy.build());
+
+    if let Some(request_timeout) = overrides.request_timeout.or(options.request_timeout) {
+        profile = profile.request_timeout(Some(Duration::from_millis(request_timeout)));
+    }
+
+    if let Some(consistency) = overrides.consistency.as_ref().or(options.consistency.as_ref()) {
+        profile = profile.consistency(parse_consistency(consistency)?);
+    }
+
+    if let Some(serial_consistency) = &overrides.serial_consistency {
+        profile = profile.serial_consistency(Some(parse_serial_consistency(serial_consistency)?));
+    }
+
+    if let Some(retry_policy) = overrides.retry_policy.as_ref().or(options.retry_policy.as_ref()) {
+        profile = profile.retry_policy(parse_retry_policy(retry_policy)?);
+    }
+
+    if let Some(speculative_execution) = overrides
+        .speculative_execution
+        .as_ref()
+        .or(options.speculative_execution.as_ref())
+    {
+        profile = profile.speculative_execution_policy(Some(build_speculative_execution(speculative_execution)?));
+    }
+
+    Ok(profile.build())
+}
+
+pub async fn build_session(connect_data: &ConnectData) -> error::Result<ScyllaSession> {
+    if connect_data.contact_points.is_empty() {
+        return Err(invalid("At least one contact point is required"));
+    }
+
+    let default_options = SessionOptions::default();
+    let options = connect_data.options.as_deref().unwrap_or(&default_options);
+
+    let local_dc = if connect_data.local_data_center.is_empty() {
+        None
+    } else {
+        Some(connect_data.local_data_center.clone())
+    };
+
+    let profile = build_execution_profile(connect_data, options, &ExecutionProfileOptions::default())?;
+
+    let mut profiles = HashMap::new();
+
+    for (name, overrides) in connect_data.execution_profiles.iter().flatten() {
+        let profile = build_execution_profile(connect_data, options, overrides)
+            .map_err(|e| Error::InvalidData(format!("Execution profile {}: {}", name, e)))?;
+
+        profiles.insert(name.to_string(), profile.into_handle());
+    }
+
+    let mut builder = SessionBuilder::new()
+        .known_nodes(&connect_data.contact_points)
+        .user(
+            &connect_data.credentials.username,
+            &connect_data.credentials.password,
+        )
+        .default_execution_profile_handle(profile.into_handle());
+
+    let allowed_racks = connect_data.allowed_racks.as_deref().unwrap_or_default();
+    let allowed_hosts = connect_data.allowed_hosts.as_deref().unwrap_or_default();
+
+    if !allowed_racks.is_empty() || !allowed_hosts.is_empty() {
+        let filter = AllowListFilter::new(local_dc, allowed_racks, allowed_hosts).await?;
+
+        builder = builder.host_filter(Arc::new(filter));
+    }
+
+    if let Some(connection_timeout) = options.connection_timeout {
+        builder = builder.connection_timeout(Duration::from_millis(connection_timeout));
+    }
+
+    if let Some(compression) = &options.compression {
+        builder = builder.compression(parse_compression(compression)?);
+    }
+
+    if let Some(tls) = &options.tls {
+        builder = builder.ssl_context(Some(build_cluster_context(tls)?));
+    }
+
+    if let Some(connections_per_shard) = options.connections_per_shard {
+        let connections_per_shard = NonZeroUsize::new(connections_per_shard)
+            .ok_or_else(|| invalid("connectionsPerShard has to be at least 1"))?;
+
+        builder = builder.pool_size(PoolSize::PerShard(connections_per_shard));
+    }
+
+    if let Some(keepalive_interval) = options.keepalive_interval {
+        builder = builder.keepalive_interval(Duration::from_millis(keepalive_interval));
+    }
+
+    if let Some(keepalive_timeout) = options.keepalive_timeout {
+        builder = builder.keepalive_timeout(Duration::from_millis(keepalive_timeout));
+    }
+
+    if let Some(tcp_keepalive_interval) = options.tcp_keepalive_interval {
+        builder = builder.tcp_keepalive_interval(Duration::from_millis(tcp_keepalive_interval));
+    }
+
+    if let Some(schema_agreement_timeout) = options.schema_agreement_timeout {
+        builder = builder.schema_agreement_timeout(Duration::from_millis(schema_agreement_timeout));
+    }
+
+    let session = builder
+        .build()
+        .await
+        .map_err(|e| Error::ConnectFailed(e.to_string()))?;
+
+    Ok(ScyllaSession { session, profiles })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{build_speculative_execution, parse_consistency, parse_retry_policy, session_key};
+    use crate::structs::connect::{ConnectData, SpeculativeExecutionOptions};
+    use scylla::statement::Consistency;
+
+    fn connect_data(keyspace: &str, username: &str) -> ConnectData {
+        serde_json::from_value(serde_json::json!({
+            "contactPoints": ["10.0.0.1:9042"],
+            "localDataCenter": "dc1",
+            "credentials": { "username": username, "password": "secret" },
+            "keyspace": keyspace,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn session_key_ignores_the_keyspace() {
+        assert_eq!(session_key(&connect_data("one", "alice")), session_key(&connect_data("two", "alice")));
+        assert_ne!(session_key(&connect_data("one", "alice")), session_key(&connect_data("one", "bob")));
+    }
+
+    #[test]
+    fn consistency_ignores_case_and_separators() {
+        assert_eq!(parse_consistency("LOCAL_QUORUM").unwrap(), Consistency::LocalQuorum);
+        assert_eq!(parse_consistency("local-one").unwrap(), Consistency::LocalOne);
+        assert_eq!(parse_consistency("eachQuorum").unwrap(), Consistency::EachQuorum);
+        assert!(parse_consistency("most").is_err());
+    }
+
+    #[test]
+    fn retry_policies_by_name() {
+        assert!(format!("{:?}", parse_retry_policy("none").unwrap()).contains("Fallthrough"));
+        assert!(format!("{:?}", parse_retry_policy("Default").unwrap()).contains("DefaultRetryPolicy"));
+        assert!(format!("{:?}", parse_retry_policy("downgrading_consistency").unwrap()).contains("Downgrading"));
+        assert!(parse_retry_policy("forever").is_err());
+    }
+
+    fn speculative_execution(
+        policy: &str,
+        retry_interval: Option<u64>,
+        percentile: Option<f64>,
+    ) -> SpeculativeExecutionOptions {
+        SpeculativeExecutionOptions {
+            policy: policy.to_string(),
+            max_retry_count: 2,
+            retry_interval,
+            percentile,
+        }
+    }
+
+    #[test]
+    fn speculative_execution_requires_the_options_of_its_policy() {
+        assert!(build_speculative_execution(&speculative_execution("simple", Some(100), None)).is_ok());
+        assert!(build_speculative_execution(&speculative_execution("simple", None, Some(99.0))).is_err());
+        assert!(build_speculative_execution(&speculative_execution("percentile", None, Some(99.0))).is_ok());
+        assert!(build_speculative_execution(&speculative_execution("percentile", Some(100), None)).is_err());
+        assert!(build_speculative_execution(&speculative_execution("eager", Some(100), Some(99.0))).is_err());
+    }
+
+    #[test]
+    fn speculative_execution_percentile_has_to_be_in_range() {
+        for percentile in [0.0, 50.0, 99.9] {
+            assert!(build_speculative_execution(&speculative_execution("percentile", None, Some(percentile))).is_ok());
+        }
+
+        for percentile in [-1.0, 100.0, 250.0, f64::NAN] {
+            assert!(build_speculative_execution(&speculative_execution("percentile", None, Some(percentile))).is_err());
+        }
+    }
+}
@@ -0,0 +1,174 @@
+use serde::Serialize;
+use std::{sync::OnceLock, time::Duration};
+
+use crate::{
+    config::SlowQueryLogConfig,
+    error,
+    structs::common::Value,
+    util::ndjson::NdjsonWriter,
+};
+
+struct SlowQueryLog {
+    threshold: Duration,
+    redact_values: bool,
+    writer: NdjsonWriter,
+}
+
+static SLOW_LOG: OnceLock<SlowQueryLog> = OnceLock::new();
+
+pub fn init(config: &SlowQueryLogConfig) -> error::Result<()> {
+    let _ = SLOW_LOG.set(SlowQueryLog::open(config)?);
+
+    Ok(())
+}
+
+// ? Everything we know about a statement once its response was sent
+pub struct SlowQuery<'a> {
+    pub connection: &'a str,
+    pub command: &'a str,
+    pub nonce: Option<&'a str>,
+    pub cql: &'a str,
+    pub values: &'a [&'a Value],
+    pub rows: usize,
+    pub response_bytes: usize,
+    pub duration: Duration,
+}
+
+#[derive(Serialize)]
+struct SlowQueryRecord<'a> {
+    timestamp: String,
+    connection: &'a str,
+    command: &'a str,
+    nonce: Option<&'a str>,
+    cql: &'a str,
+    #[serde(rename = "valueTypes")]
+    value_types: Vec<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    values: Option<&'a [&'a Value]>,
+    rows: usize,
+    #[serde(rename = "responseBytes")]
+    response_bytes: usize,
+    #[serde(rename = "durationMs")]
+    duration_ms: f64,
+}
+
+impl SlowQueryLog {
+    fn open(config: &SlowQueryLogConfig) -> error::Result<Self> {
+        Ok(Self {
+            threshold: Duration::from_millis(config.threshold),
+            redact_values: config.redact_values,
+            writer: NdjsonWriter::open(&config.path, &config.rotation)?,
+        })
+    }
+
+    async fn record(&self, query: SlowQuery<'_>) {
+        if query.duration < self.threshold {
+            return;
+        }
+
+        let record = SlowQueryRecord {
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            connection: query.connection,
+            command: query.command,
+            nonce: query.nonce,
+            cql: query.cql,
+            value_types: query.values.iter().map(|value| value.type_name()).collect(),
+            values: if self.redact_values { None } else { Some(query.values) },
+            rows: query.rows,
+            response_bytes: query.response_bytes,
+            duration_ms: query.duration.as_secs_f64() * 1000.0,
+        };
+
+        self.writer.write(&record).await;
+    }
+}
+
+// ? Only writes the statement if the slow query log is on and it took longer than the threshold
+pub async fn record(query: SlowQuery<'_>) {
+    if let Some(slow_log) = SLOW_LOG.get() {
+        slow_log.record(query).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SlowQuery, SlowQueryLog};
+    use crate::{
+        config::{RotationConfig, SlowQueryLogConfig},
+        structs::common::Value,
+    };
+    use std::time::Duration;
+
+    fn open(name: &str, redact_values: bool) -> (SlowQueryLog, String) {
+        let path = std::env::temp_dir()
+            .join(format!("scyllatcp-{}-{}.ndjson", name, std::process::id()))
+            .to_string_lossy()
+            .to_string();
+
+        let _ = std::fs::remove_file(&path);
+
+        let config = SlowQueryLogConfig {
+            path: path.clone(),
+            rotation: RotationConfig::default(),
+            threshold: 100,
+            redact_values,
+        };
+
+        (SlowQueryLog::open(&config).unwrap(), path)
+    }
+
+    fn query<'a>(values: &'a [&'a Value], duration_ms: u64) -> SlowQuery<'a> {
+        SlowQuery {
+            connection: "1",
+            command: "select",
+            nonce: Some("n"),
+            cql: "SELECT * FROM ks.t WHERE id = ?",
+            values,
+            rows: 1,
+            response_bytes: 10,
+            duration: Duration::from_millis(duration_ms),
+        }
+    }
+
+    fn records(path: &str) -> Vec<serde_json::Value> {
+        let records = std::fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        let _ = std::fs::remove_file(path);
+
+        records
+    }
+
+    #[tokio::test]
+    async fn only_statements_past_the_threshold_are_logged() {
+        let (slow_log, path) = open("threshold", false);
+        let id = Value::Str("secret".to_string());
+
+        slow_log.record(query(&[&id], 99)).await;
+        slow_log.record(query(&[&id], 100)).await;
+
+        let records = records(&path);
+
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0]["durationMs"], 100.0);
+        assert_eq!(records[0]["values"], serde_json::json!(["secret"]));
+        assert_eq!(records[0]["valueTypes"], serde_json::json!(["string"]));
+    }
+
+    #[tokio::test]
+    async fn redacted_values_only_keep_their_types() {
+        let (slow_log, path) = open("redact", true);
+        let id = Value::Str("secret".to_string());
+
+        slow_log.record(query(&[&id], 250)).await;
+
+        let records = records(&path);
+
+        assert_eq!(records.len(), 1);
+        assert!(records[0].get("values").is_none());
+        assert_eq!(records[0]["valueTypes"], serde_json::json!(["string"]));
+    }
+}
@@ -0,0 +1,206 @@
+use scylla::{
+    history::{AttemptResult, FiberHistory, HistoryCollector, StructuredHistory}, query::Query, serialize::row::SerializeRow,
+    transport::errors::QueryError, QueryResult,
+};
+use std::{
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::{
+    error::{self, Error},
+    metrics,
+    structs::{
+        common::{Command, ExecutionInfo, Stats},
+        tracing::{TracingData, TracingEventData},
+    },
+    util::session::{parse_retry_policy, ScyllaSession},
+};
+
+pub struct Execution {
+    pub result: Result<QueryResult, QueryError>,
+    pub info: ExecutionInfo,
+    pub tracing: Option<TracingData>,
+    pub duration: Duration, // ? How long the driver took, including retries
+    pub timing: Timing,
+}
+
+// ? Kept apart from the result so it can still be used once the result was moved out
+pub struct Timing {
+    pub started: Instant,
+    pub finished: Instant, // ? Once the trace was fetched as well
+    pub coordinator: Option<SocketAddr>,
+}
+
+impl Timing {
+    // ? None unless the client asked for stats, serialization only covers the time until now (the encoding is added when sending)
+    pub fn stats(&self, command: &Command, info: &ExecutionInfo, rows: usize) -> Option<Stats> {
+        if !command.stats.unwrap_or(false) {
+            return None;
+        }
+
+        let queue = match command.received_at {
+            Some(received_at) => self.started.saturating_duration_since(received_at),
+            None => Duration::ZERO,
+        };
+
+        Some(Stats {
+            queue_ms: queue.as_secs_f64() * 1000.0,
+            execution_ms: (self.finished - self.started).as_secs_f64() * 1000.0,
+            serialization_ms: self.finished.elapsed().as_secs_f64() * 1000.0,
+            coordinator: self.coordinator.map(|coordinator| coordinator.to_string()),
+            attempts: info.attempts,
+            rows,
+            bytes: 0,
+        })
+    }
+}
+
+// ? Every handler builds its statement here, so the per request settings are applied the same way everywhere
+// ? idempotent is what we assume when the client doesn't say (e.g selects are always safe to retry)
+pub fn build_statement(
+    query: &str,
+    command: &Command,
+    session: &ScyllaSession,
+    idempotent: bool,
+) -> error::Result<Query> {
+    let mut statement = Query::new(query.to_string());
+
+    if let Some(name) = &command.profile {
+        let handle = session
+            .profiles
+            .get(name)
+            .ok_or_else(|| Error::InvalidStatement(format!("Unknown execution profile: {}", name)))?;
+
+        statement.set_execution_profile_handle(Some(handle.clone()));
+    }
+
+    statement.set_is_idempotent(command.idempotent.unwrap_or(idempotent));
+
+    statement.set_tracing(command.trace.unwrap_or(false));
+
+    if let Some(retry_policy) = &command.retry_policy {
+        let retry_policy = parse_retry_policy(retry_policy).map_err(|e| Error::InvalidStatement(e.to_string()))?;
+
+        statement.set_retry_policy(Some(Arc::from(retry_policy)));
+    }
+
+    Ok(statement)
+}
+
+// ? Runs the statement with a history listener, so we can tell the client how often it was sent
+pub async fn execute(session: &ScyllaSession, mut statement: Query, values: impl SerializeRow) -> Execution {
+    let _in_flight = metrics::InFlight::start();
+    let history = Arc::new(HistoryCollector::new());
+
+    statement.set_history_listener(history.clone());
+
+    let started = Instant::now();
+    let result = session.session.query(statement, values).await;
+    let duration = started.elapsed();
+
+    let history = history.clone_structured_history();
+
+    let attempts = history
+        .queries
+        .iter()
+        .map(|query| {
+            query.non_speculative_fiber.attempts.len()
+                + query
+                    .speculative_fibers
+                    .iter()
+                    .map(|fiber| fiber.attempts.len())
+                    .sum::<usize>()
+        })
+        .sum();
+
+    let succeeded = |fiber: &FiberHistory| {
+        fiber
+            .attempts
+            .iter()
+            .any(|attempt| matches!(attempt.result, Some(AttemptResult::Success(_))))
+    };
+
+    let speculative_won = history
+        .queries
+        .iter()
+        .any(|query| query.speculative_fibers.iter().any(succeeded));
+
+    let coordinator = coordinator(&history);
+
+    if let Ok(result) = &result {
+        for warning in &result.warnings {
+            warn!(warning = %warning, "Scylla returned a warning");
+        }
+    }
+
+    let tracing_id = result.as_ref().ok().and_then(|result| result.tracing_id);
+
+    let tracing = match tracing_id {
+        Some(tracing_id) => fetch_tracing(session, &tracing_id).await,
+        None => None,
+    };
+
+    Execution {
+        result,
+        info: ExecutionInfo {
+            attempts,
+            speculative_won,
+        },
+        tracing,
+        duration,
+        timing: Timing {
+            started,
+            finished: Instant::now(),
+            coordinator,
+        },
+    }
+}
+
+// ? The node of the attempt that succeeded, else the last one that was tried
+fn coordinator(history: &StructuredHistory) -> Option<SocketAddr> {
+    let tried = history.queries.iter().flat_map(|query| {
+        std::iter::once(&query.non_speculative_fiber)
+            .chain(query.speculative_fibers.iter())
+            .flat_map(|fiber| fiber.attempts.iter())
+    });
+
+    tried
+        .clone()
+        .find(|attempt| matches!(attempt.result, Some(AttemptResult::Success(_))))
+        .or_else(|| tried.last())
+        .map(|attempt| attempt.node_addr)
+}
+
+// ? The driver waits for the trace to be complete, a trace we can't get isn't worth failing the command over
+async fn fetch_tracing(session: &ScyllaSession, tracing_id: &Uuid) -> Option<TracingData> {
+    let tracing_info = match session.session.get_tracing_info(tracing_id).await {
+        Ok(tracing_info) => tracing_info,
+        Err(e) => {
+            warn!("Failed to fetch the trace {}: {}", tracing_id, e);
+
+            return None;
+        }
+    };
+
+    let events = tracing_info
+        .events
+        .into_iter()
+        .map(|event| TracingEventData {
+            activity: event.activity,
+            source: event.source.map(|source| source.to_string()),
+            source_elapsed: event.source_elapsed,
+            thread: event.thread,
+        })
+        .collect();
+
+    Some(TracingData {
+        id: tracing_id.to_string(),
+        coordinator: tracing_info.coordinator.map(|coordinator| coordinator.to_string()),
+        duration: tracing_info.duration,
+        events,
+    })
+}